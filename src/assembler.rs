@@ -0,0 +1,648 @@
+use crate::cpu::INITIAL_POSITION;
+use crate::instruction::Instruction;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Names that read as operands and so can't be used for labels or constants.
+const RESERVED_NAMES: [&str; 9] = ["I", "DT", "ST", "K", "F", "HF", "B", "R", "LONG"];
+
+const MNEMONICS: [&str; 31] = [
+    "CLS", "RET", "SCD", "SCU", "SCR", "SCL", "EXIT", "LOW", "HIGH", "JP", "CALL", "SE", "SNE",
+    "SAVE", "LOAD", "LD", "ADD", "OR", "AND", "XOR", "SUB", "SHR", "SUBN", "SHL", "RND", "DRW",
+    "SKP", "SKNP", "PLANE", "AUDIO", "PITCH",
+];
+const DIRECTIVES: [&str; 4] = ["DB", "DW", "EQU", "ORG"];
+
+/// Where in the source assembling failed, counting lines and columns from 1.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AssemblyError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssemblyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+/// Assembles classic mnemonic syntax into a ROM to load at 0x200, or at the
+/// address of an `ORG` directive placed before the first instruction.
+///
+/// Every line holds an optional `label:`, then an instruction such as
+/// `DRW V1, V2, 5`, a `DB`/`DW` directive with comma separated values, or a
+/// `NAME EQU value` constant. Values are decimal, `0x` hexadecimal or `0b`
+/// binary numbers, labels and constants, joined with `+` and `-`. Constants
+/// can only use names defined above them. `;` starts a comment.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssemblyError> {
+    let mut assembler = Assembler::default();
+    let statements = assembler.parse(source)?;
+    let mut rom = Vec::new();
+    for statement in &statements {
+        assembler.encode(statement, &mut rom)?;
+    }
+    Ok(rom)
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Token<'a> {
+    text: &'a str,
+    column: usize,
+}
+
+impl Token<'_> {
+    fn is(&self, text: &str) -> bool {
+        self.text.eq_ignore_ascii_case(text)
+    }
+}
+
+struct Statement<'a> {
+    line: usize,
+    mnemonic: Token<'a>,
+    operands: Vec<Vec<Token<'a>>>,
+}
+
+/// A value with the place it came from, so range errors can point at it.
+#[derive(Clone, Copy, Debug)]
+struct Number {
+    value: i64,
+    line: usize,
+    column: usize,
+}
+
+impl Number {
+    fn fit(&self, min: i64, max: i64) -> Result<i64, AssemblyError> {
+        if (min..=max).contains(&self.value) {
+            Ok(self.value)
+        } else {
+            Err(error(
+                self.line,
+                self.column,
+                format!("Value {} out of range {min} to {max}", self.value),
+            ))
+        }
+    }
+
+    fn nibble(&self) -> Result<u8, AssemblyError> {
+        Ok(self.fit(0, 0xF)? as u8)
+    }
+
+    /// Negative values are stored as two's complement.
+    fn byte(&self) -> Result<u8, AssemblyError> {
+        Ok(self.fit(-0x80, 0xFF)? as u8)
+    }
+
+    fn address(&self) -> Result<u16, AssemblyError> {
+        Ok(self.fit(0, 0xFFF)? as u16)
+    }
+
+    fn word(&self) -> Result<u16, AssemblyError> {
+        Ok(self.fit(-0x8000, 0xFFFF)? as u16)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Operand {
+    Register(u8),
+    Index,
+    /// `[I]`
+    IndirectIndex,
+    DelayTimer,
+    SoundTimer,
+    Key,
+    Font,
+    BigFont,
+    Bcd,
+    Flags,
+    /// `LONG value`, the XO-CHIP 16 bit address.
+    Long(Number),
+    Value(Number),
+}
+
+#[derive(Default)]
+struct Assembler {
+    symbols: HashMap<String, i64>,
+}
+
+impl Assembler {
+    /// First pass: splits lines into statements and gives every label its
+    /// address.
+    fn parse<'a>(&mut self, source: &'a str) -> Result<Vec<Statement<'a>>, AssemblyError> {
+        let mut address = INITIAL_POSITION as i64;
+        let mut statements = Vec::new();
+        for (line_index, text) in source.lines().enumerate() {
+            let line = line_index + 1;
+            let mut tokens = tokenize(text);
+            if tokens.len() >= 2 && tokens[1].text == ":" {
+                self.define(line, tokens[0], address, true)?;
+                tokens.drain(..2);
+            }
+            let Some(mnemonic) = tokens.first().copied() else {
+                continue;
+            };
+            if tokens.get(1).is_some_and(|token| token.is("EQU")) {
+                let value = self.evaluate(line, tokens[1], &tokens[2..])?;
+                self.define(line, mnemonic, value.value, false)?;
+                continue;
+            }
+            if mnemonic.is("ORG") {
+                if !statements.is_empty() {
+                    return Err(error(
+                        line,
+                        mnemonic.column,
+                        String::from("ORG must come before the first instruction"),
+                    ));
+                }
+                let origin = self.evaluate(line, mnemonic, &tokens[1..])?;
+                address = origin.fit(0, 0xFFFF)?;
+                continue;
+            }
+            let operands = split_operands(line, &tokens)?;
+            let size = if mnemonic.is("DB") {
+                operands.len() as i64
+            } else if mnemonic.is("DW") {
+                operands.len() as i64 * 2
+            } else if operands.get(1).is_some_and(|operand| operand[0].is("LONG")) {
+                4
+            } else {
+                2
+            };
+            address += size;
+            statements.push(Statement {
+                line,
+                mnemonic,
+                operands,
+            });
+        }
+        Ok(statements)
+    }
+
+    /// Gives `name` a value. Labels end in `:`, so unlike constants they can
+    /// share the name of an instruction.
+    fn define(
+        &mut self,
+        line: usize,
+        name: Token,
+        value: i64,
+        is_label: bool,
+    ) -> Result<(), AssemblyError> {
+        let is_keyword = || {
+            MNEMONICS
+                .iter()
+                .chain(&DIRECTIVES)
+                .any(|keyword| name.is(keyword))
+        };
+        let is_valid = name
+            .text
+            .starts_with(|character: char| character.is_ascii_alphabetic() || character == '_')
+            && parse_register(name.text).is_none()
+            && !RESERVED_NAMES.iter().any(|reserved| name.is(reserved))
+            && (is_label || !is_keyword());
+        if !is_valid {
+            return Err(error(
+                line,
+                name.column,
+                format!("Invalid name {}", name.text),
+            ));
+        }
+        if self.symbols.insert(name.text.to_string(), value).is_some() {
+            return Err(error(
+                line,
+                name.column,
+                format!("{} is already defined", name.text),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Second pass: turns a statement into bytes now that every label is
+    /// known.
+    fn encode(&self, statement: &Statement, rom: &mut Vec<u8>) -> Result<(), AssemblyError> {
+        let line = statement.line;
+        let mnemonic = statement.mnemonic;
+        if mnemonic.is("DB") || mnemonic.is("DW") {
+            for operand in &statement.operands {
+                let number = self.evaluate(line, operand[0], operand)?;
+                if mnemonic.is("DB") {
+                    rom.push(number.byte()?);
+                } else {
+                    rom.extend(number.word()?.to_be_bytes());
+                }
+            }
+            return Ok(());
+        }
+        let operands = statement
+            .operands
+            .iter()
+            .map(|operand| self.operand(line, operand))
+            .collect::<Result<Vec<_>, _>>()?;
+        let name = mnemonic.text.to_ascii_uppercase();
+        let instruction = match (name.as_str(), operands.as_slice()) {
+            ("CLS", []) => Instruction::ClearScreen,
+            ("RET", []) => Instruction::Return,
+            ("SCD", [Operand::Value(n)]) => Instruction::ScrollDown(n.nibble()?),
+            ("SCU", [Operand::Value(n)]) => Instruction::ScrollUp(n.nibble()?),
+            ("SCR", []) => Instruction::ScrollRight,
+            ("SCL", []) => Instruction::ScrollLeft,
+            ("EXIT", []) => Instruction::Exit,
+            ("LOW", []) => Instruction::LowRes,
+            ("HIGH", []) => Instruction::HighRes,
+            ("JP", [Operand::Value(address)]) => Instruction::Jump(address.address()?),
+            ("JP", [Operand::Register(0), Operand::Value(address)]) => {
+                let address = address.address()?;
+                Instruction::JumpOffset {
+                    address,
+                    x: (address >> 8) as u8,
+                }
+            }
+            ("CALL", [Operand::Value(address)]) => Instruction::Call(address.address()?),
+            ("SE", [Operand::Register(x), Operand::Value(value)]) => Instruction::SkipIfEqual {
+                x: *x,
+                value: value.byte()?,
+            },
+            ("SE", [Operand::Register(x), Operand::Register(y)]) => {
+                Instruction::SkipIfRegistersEqual { x: *x, y: *y }
+            }
+            ("SNE", [Operand::Register(x), Operand::Value(value)]) => Instruction::SkipIfNotEqual {
+                x: *x,
+                value: value.byte()?,
+            },
+            ("SNE", [Operand::Register(x), Operand::Register(y)]) => {
+                Instruction::SkipIfRegistersNotEqual { x: *x, y: *y }
+            }
+            ("SAVE", [Operand::Register(x), Operand::Register(y)]) => {
+                Instruction::SaveRange { x: *x, y: *y }
+            }
+            ("LOAD", [Operand::Register(x), Operand::Register(y)]) => {
+                Instruction::LoadRange { x: *x, y: *y }
+            }
+            ("LD", [Operand::Register(x), Operand::Value(value)]) => Instruction::Load {
+                x: *x,
+                value: value.byte()?,
+            },
+            ("LD", [Operand::Register(x), Operand::Register(y)]) => {
+                Instruction::Move { x: *x, y: *y }
+            }
+            ("LD", [Operand::Index, Operand::Value(address)]) => {
+                Instruction::LoadIndex(address.address()?)
+            }
+            ("LD", [Operand::Index, Operand::Long(address)]) => {
+                rom.extend(Instruction::LoadLongIndex.encode().to_be_bytes());
+                rom.extend(address.word()?.to_be_bytes());
+                return Ok(());
+            }
+            ("LD", [Operand::Register(x), Operand::DelayTimer]) => Instruction::GetDelayTimer(*x),
+            ("LD", [Operand::Register(x), Operand::Key]) => Instruction::WaitKey(*x),
+            ("LD", [Operand::DelayTimer, Operand::Register(x)]) => Instruction::SetDelayTimer(*x),
+            ("LD", [Operand::SoundTimer, Operand::Register(x)]) => Instruction::SetSoundTimer(*x),
+            ("LD", [Operand::Font, Operand::Register(x)]) => Instruction::LoadFont(*x),
+            ("LD", [Operand::BigFont, Operand::Register(x)]) => Instruction::LoadBigFont(*x),
+            ("LD", [Operand::Bcd, Operand::Register(x)]) => Instruction::StoreBcd(*x),
+            ("LD", [Operand::IndirectIndex, Operand::Register(x)]) => {
+                Instruction::StoreRegisters(*x)
+            }
+            ("LD", [Operand::Register(x), Operand::IndirectIndex]) => {
+                Instruction::LoadRegisters(*x)
+            }
+            ("LD", [Operand::Flags, Operand::Register(x)]) => Instruction::StoreFlags(*x),
+            ("LD", [Operand::Register(x), Operand::Flags]) => Instruction::LoadFlags(*x),
+            ("ADD", [Operand::Register(x), Operand::Value(value)]) => Instruction::Add {
+                x: *x,
+                value: value.byte()?,
+            },
+            ("ADD", [Operand::Register(x), Operand::Register(y)]) => {
+                Instruction::AddRegisters { x: *x, y: *y }
+            }
+            ("ADD", [Operand::Index, Operand::Register(x)]) => Instruction::AddToIndex(*x),
+            ("OR", [Operand::Register(x), Operand::Register(y)]) => {
+                Instruction::Or { x: *x, y: *y }
+            }
+            ("AND", [Operand::Register(x), Operand::Register(y)]) => {
+                Instruction::And { x: *x, y: *y }
+            }
+            ("XOR", [Operand::Register(x), Operand::Register(y)]) => {
+                Instruction::Xor { x: *x, y: *y }
+            }
+            ("SUB", [Operand::Register(x), Operand::Register(y)]) => {
+                Instruction::Subtract { x: *x, y: *y }
+            }
+            ("SUBN", [Operand::Register(x), Operand::Register(y)]) => {
+                Instruction::SubtractReversed { x: *x, y: *y }
+            }
+            ("SHR", [Operand::Register(x)]) => Instruction::ShiftRight { x: *x, y: *x },
+            ("SHR", [Operand::Register(x), Operand::Register(y)]) => {
+                Instruction::ShiftRight { x: *x, y: *y }
+            }
+            ("SHL", [Operand::Register(x)]) => Instruction::ShiftLeft { x: *x, y: *x },
+            ("SHL", [Operand::Register(x), Operand::Register(y)]) => {
+                Instruction::ShiftLeft { x: *x, y: *y }
+            }
+            ("RND", [Operand::Register(x), Operand::Value(mask)]) => Instruction::Random {
+                x: *x,
+                mask: mask.byte()?,
+            },
+            ("DRW", [Operand::Register(x), Operand::Register(y), Operand::Value(rows)]) => {
+                Instruction::Draw {
+                    x: *x,
+                    y: *y,
+                    rows: rows.nibble()?,
+                }
+            }
+            ("SKP", [Operand::Register(x)]) => Instruction::SkipIfKey(*x),
+            ("SKNP", [Operand::Register(x)]) => Instruction::SkipIfNotKey(*x),
+            ("PLANE", [Operand::Value(planes)]) => Instruction::SelectPlanes(planes.nibble()?),
+            ("AUDIO", []) => Instruction::LoadAudioPattern,
+            ("PITCH", [Operand::Register(x)]) => Instruction::SetPitch(*x),
+            (name, _) if MNEMONICS.contains(&name) => {
+                return Err(error(
+                    line,
+                    mnemonic.column,
+                    format!("Invalid operands for {name}"),
+                ))
+            }
+            _ => {
+                return Err(error(
+                    line,
+                    mnemonic.column,
+                    format!("Unknown instruction {}", mnemonic.text),
+                ))
+            }
+        };
+        rom.extend(instruction.encode().to_be_bytes());
+        Ok(())
+    }
+
+    fn operand(&self, line: usize, tokens: &[Token]) -> Result<Operand, AssemblyError> {
+        let first = tokens[0];
+        if let [token] = tokens {
+            if let Some(register) = parse_register(token.text) {
+                return Ok(Operand::Register(register));
+            }
+            let special = match token.text.to_ascii_uppercase().as_str() {
+                "I" => Some(Operand::Index),
+                "DT" => Some(Operand::DelayTimer),
+                "ST" => Some(Operand::SoundTimer),
+                "K" => Some(Operand::Key),
+                "F" => Some(Operand::Font),
+                "HF" => Some(Operand::BigFont),
+                "B" => Some(Operand::Bcd),
+                "R" => Some(Operand::Flags),
+                _ => None,
+            };
+            if let Some(special) = special {
+                return Ok(special);
+            }
+        }
+        if let [open, index, close] = tokens {
+            if open.text == "[" && index.is("I") && close.text == "]" {
+                return Ok(Operand::IndirectIndex);
+            }
+        }
+        if first.is("LONG") {
+            return Ok(Operand::Long(self.evaluate(line, first, &tokens[1..])?));
+        }
+        Ok(Operand::Value(self.evaluate(line, first, tokens)?))
+    }
+
+    /// Evaluates terms joined with `+` and `-`. `start` locates the error when
+    /// `tokens` is empty.
+    fn evaluate(
+        &self,
+        line: usize,
+        start: Token,
+        tokens: &[Token],
+    ) -> Result<Number, AssemblyError> {
+        let column = tokens.first().unwrap_or(&start).column;
+        let mut value: i64 = 0;
+        let mut sign = 1;
+        let mut expects_term = true;
+        for token in tokens {
+            match (token.text, expects_term) {
+                ("+", false) => sign = 1,
+                ("-", false) => sign = -1,
+                ("-", true) => {
+                    sign = -sign;
+                    continue;
+                }
+                (_, true) => {
+                    value = self
+                        .term(line, *token)?
+                        .checked_mul(sign)
+                        .and_then(|term| value.checked_add(term))
+                        .ok_or_else(|| error(line, token.column, String::from("Value too large")))?
+                }
+                _ => {
+                    return Err(error(
+                        line,
+                        token.column,
+                        format!("Unexpected {}", token.text),
+                    ))
+                }
+            }
+            expects_term = !expects_term;
+        }
+        if expects_term {
+            let end = tokens.last().unwrap_or(&start);
+            return Err(error(
+                line,
+                end.column + end.text.len(),
+                String::from("Missing value"),
+            ));
+        }
+        Ok(Number {
+            value,
+            line,
+            column,
+        })
+    }
+
+    fn term(&self, line: usize, token: Token) -> Result<i64, AssemblyError> {
+        let text = token.text;
+        if text.starts_with(|character: char| character.is_ascii_digit()) {
+            let (digits, radix) = if let Some(digits) =
+                text.strip_prefix("0x").or_else(|| text.strip_prefix("0X"))
+            {
+                (digits, 16)
+            } else if let Some(digits) = text.strip_prefix("0b").or_else(|| text.strip_prefix("0B"))
+            {
+                (digits, 2)
+            } else {
+                (text, 10)
+            };
+            i64::from_str_radix(digits, radix)
+                .map_err(|_| error(line, token.column, format!("Invalid number {text}")))
+        } else {
+            self.symbols
+                .get(text)
+                .copied()
+                .ok_or_else(|| error(line, token.column, format!("Unknown name {text}")))
+        }
+    }
+}
+
+fn tokenize(line: &str) -> Vec<Token<'_>> {
+    let code = line.split(';').next().unwrap_or_default();
+    let mut tokens = Vec::new();
+    let mut characters = code.char_indices().peekable();
+    while let Some((start, character)) = characters.next() {
+        if character.is_whitespace() {
+            continue;
+        }
+        let mut end = start + character.len_utf8();
+        if is_word_character(character) {
+            while let Some((index, next)) = characters.peek().copied() {
+                if !is_word_character(next) {
+                    break;
+                }
+                end = index + next.len_utf8();
+                characters.next();
+            }
+        }
+        tokens.push(Token {
+            text: &code[start..end],
+            column: start + 1,
+        });
+    }
+    tokens
+}
+
+fn is_word_character(character: char) -> bool {
+    character.is_ascii_alphanumeric() || character == '_' || character == '.'
+}
+
+/// Splits the tokens after the mnemonic on commas.
+fn split_operands<'a>(
+    line: usize,
+    tokens: &[Token<'a>],
+) -> Result<Vec<Vec<Token<'a>>>, AssemblyError> {
+    let mut operands = Vec::new();
+    if tokens.len() == 1 {
+        return Ok(operands);
+    }
+    let mut operand = Vec::new();
+    let mut previous = tokens[0];
+    for token in &tokens[1..] {
+        if token.text == "," {
+            if operand.is_empty() {
+                return Err(error(line, token.column, String::from("Missing operand")));
+            }
+            operands.push(std::mem::take(&mut operand));
+        } else {
+            operand.push(*token);
+        }
+        previous = *token;
+    }
+    if operand.is_empty() {
+        return Err(error(
+            line,
+            previous.column + 1,
+            String::from("Missing operand"),
+        ));
+    }
+    operands.push(operand);
+    Ok(operands)
+}
+
+fn parse_register(text: &str) -> Option<u8> {
+    let digit = text.strip_prefix(['V', 'v'])?;
+    if digit.len() == 1 {
+        u8::from_str_radix(digit, 16).ok()
+    } else {
+        None
+    }
+}
+
+fn error(line: usize, column: usize, message: String) -> AssemblyError {
+    AssemblyError {
+        line,
+        column,
+        message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembler::disassemble;
+
+    /// CHIP-8, SCHIP and XO-CHIP instructions, a sprite and unreachable data.
+    const ROM: [u8; 26] = [
+        0x00, 0xE0, // CLS
+        0x00, 0xFF, // HIGH
+        0xA2, 0x16, // LD I, S216
+        0xF0, 0x00, 0x02, 0x18, // LD I, LONG S218
+        0xF1, 0x01, // PLANE 1
+        0xD0, 0x15, // DRW V0, V1, 5
+        0x22, 0x14, // CALL L214
+        0x12, 0x10, // JP L210
+        0x00, 0xEE, // RET, never reached
+        0x00, 0xEE, // RET
+        0xF0, 0x90, // sprite
+        0x12, 0x34, // data
+    ];
+
+    #[test]
+    fn assembles_the_disassembly_back_into_the_rom() {
+        assert_eq!(assemble(&disassemble(&ROM, 0x200)).unwrap(), ROM);
+    }
+
+    #[test]
+    fn keeps_jump_targets_of_roms_loaded_elsewhere() {
+        let rom = [0x60, 0x01, 0x16, 0x04, 0x00, 0xE0, 0x16, 0x00];
+        let listing = disassemble(&rom, 0x600);
+        assert!(listing.contains("ORG 0x600"));
+        assert_eq!(assemble(&listing).unwrap(), rom);
+    }
+
+    #[test]
+    fn resolves_labels_constants_and_data() {
+        let source = "\
+            SIZE EQU 2 + 1
+            start: LD I, LONG sprite
+                   DRW V0, V1, SIZE
+                   JP start
+            sprite: DB 0xFF, 0b1010, -1
+                    DW sprite - start";
+        assert_eq!(
+            assemble(source).unwrap(),
+            [0xF0, 0x00, 0x02, 0x08, 0xD0, 0x13, 0x12, 0x00, 0xFF, 0x0A, 0xFF, 0x00, 0x08]
+        );
+    }
+
+    #[test]
+    fn starts_at_the_origin() {
+        assert_eq!(assemble("ORG 0x600\nloop: JP loop").unwrap(), [0x16, 0x00]);
+        let error = assemble("CLS\nORG 0x600").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 1));
+    }
+
+    #[test]
+    fn accepts_labels_named_like_instructions() {
+        assert_eq!(
+            assemble("sub:\n  JP add\nadd: CALL sub").unwrap(),
+            [0x12, 0x02, 0x22, 0x00]
+        );
+        let error = assemble("ADD EQU 1").unwrap_err();
+        assert_eq!(error.message, "Invalid name ADD");
+    }
+
+    #[test]
+    fn rejects_values_too_large_to_add_up() {
+        let error = assemble("BIG EQU 0x7FFFFFFFFFFFFFFF\nDB BIG + BIG").unwrap_err();
+        assert_eq!(
+            (error.line, error.column, error.message.as_str()),
+            (2, 10, "Value too large")
+        );
+        assert!(assemble("DB 0 - BIG - 1").is_err());
+    }
+
+    #[test]
+    fn reports_where_an_error_is() {
+        let error = assemble("CLS\n  LD V0, 0x100").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 10));
+        let error = assemble("JP nowhere").unwrap_err();
+        assert_eq!((error.line, error.column), (1, 4));
+        assert_eq!(error.to_string(), "1:4: Unknown name nowhere");
+    }
+}
@@ -0,0 +1,63 @@
+/// Reads little-endian values from a binary file format, failing with a
+/// message naming the format once the data runs out.
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    format_name: &'static str,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8], format_name: &'static str) -> Self {
+        Self {
+            bytes,
+            position: 0,
+            format_name,
+        }
+    }
+
+    pub(crate) fn read_bytes(&mut self, length: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .bytes
+            .get(self.position..self.position + length)
+            .ok_or_else(|| format!("{} is truncated", self.format_name))?;
+        self.position += length;
+        Ok(bytes)
+    }
+
+    pub(crate) fn read_array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.read_bytes(N)?);
+        Ok(array)
+    }
+
+    pub(crate) fn read_u8(&mut self) -> Result<u8, String> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub(crate) fn read_u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.read_array()?))
+    }
+
+    pub(crate) fn read_u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.read_array()?))
+    }
+
+    pub(crate) fn read_u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.read_array()?))
+    }
+
+    /// Reads key states written with [`keys_to_bits`].
+    pub(crate) fn read_keys(&mut self) -> Result<[bool; 16], String> {
+        let bits = self.read_u16()?;
+        let mut keys = [false; 16];
+        for (key, is_pressed) in keys.iter_mut().enumerate() {
+            *is_pressed = bits & (1 << key) != 0;
+        }
+        Ok(keys)
+    }
+}
+
+/// Packs the state of the 16 keys into one bit each.
+pub(crate) fn keys_to_bits(keys: &[bool; 16]) -> u16 {
+    (0..16).fold(0, |bits, key| bits | (keys[key] as u16) << key)
+}
@@ -0,0 +1,421 @@
+use super::Settings;
+use chip_8::clock;
+use chip_8::config::Config;
+use chip_8::cpu::INITIAL_POSITION;
+use chip_8::debugger::{parse_number, Debugger};
+use chip_8::display::Palette;
+use chip_8::error::ErrorPolicy;
+use chip_8::keymap;
+use chip_8::movie::Movie;
+use chip_8::scheduler::Timing;
+use std::path::{Path, PathBuf};
+
+pub const HELP: &str = "\
+Usage:
+  chip-8 [run] ROM [OPTIONS]   run a ROM, - reads it from stdin
+  chip-8 test ROM [OPTIONS]    run a ROM without a window, failing on the
+                               first error or a screen that differs from
+                               --expect
+  chip-8 bench ROM [OPTIONS]   run a ROM without a window as fast as possible
+                               and report the speed
+  chip-8 disasm ROM [--load-address ADDR]
+                               print the instructions of a ROM
+  chip-8 asm SOURCE [OUTPUT]   assemble a source file, OUTPUT defaults to
+                               SOURCE with a .ch8 extension
+  chip-8 help                  show this help
+
+Options (addresses are hexadecimal):
+  --platform NAME        chip8, schip or xochip
+  --quirks NAME          quirk preset, the platform's own by default
+  --speed N              instructions per second
+  --ipf N                instructions per 60 Hz frame
+  --timing NAME          ipf, or vip for the speed of the COSMAC VIP, which
+                         also waits for the next frame on every sprite drawn
+  --colors RRGGBB,...    palette starting at the background (alias --palette)
+  --keymap KEY=NAME,...  bind CHIP-8 keys, e.g. 5=W,5=Up,8=S
+  --scale N              window pixels per CHIP-8 pixel (default 20)
+  --fullscreen           fill the screen
+  --volume N             buzzer volume from 0 to 100 (default 10)
+  --seed N               seed for random numbers
+  --config FILE          settings file instead of
+                         $XDG_CONFIG_HOME/chip-8/config.toml
+  --headless N           run N frames without a window and print the screen
+  --frames N             same as --headless
+  --screenshot FILE      save the final screen as a PPM image
+  --expect FILE          screen test compares with, as printed by --headless
+  --record FILE          record the keys pressed into a movie
+  --replay FILE          replay a recorded movie
+  --rewind SECONDS       history kept for rewinding with Backspace
+  --load-address ADDR    where the ROM is loaded and starts (default 200)
+  --stack-depth N        subroutine levels before a stack overflow (default 16)
+  --vip-stack            keep the stack in memory at EA0 like the VIP
+  --on-error POLICY      ignore, halt or debug
+  --debug                start paused in the debugger
+  --break ADDR           start in the debugger with a breakpoint
+  -h, --help             show this help";
+
+/// What the command line asks for.
+pub enum Command {
+    Run {
+        rom_name: String,
+        settings: Box<Settings>,
+    },
+    Test {
+        rom_name: String,
+        settings: Box<Settings>,
+        expected_path: Option<PathBuf>,
+    },
+    Bench {
+        rom_name: String,
+        settings: Box<Settings>,
+    },
+    Disassemble {
+        rom_name: String,
+        load_address: u16,
+    },
+    Assemble {
+        source_path: PathBuf,
+        output_path: PathBuf,
+    },
+    Help,
+}
+
+/// Reads the command from the arguments, without the program name.
+pub fn parse_command(args: &[String]) -> Result<Command, String> {
+    let Some(first) = args.first() else {
+        return Ok(Command::Help);
+    };
+    if args
+        .iter()
+        .any(|argument| argument == "-h" || argument == "--help")
+    {
+        return Ok(Command::Help);
+    }
+    match first.as_str() {
+        "help" => Ok(Command::Help),
+        "disasm" => {
+            let mut rom_name = None;
+            let mut load_address = INITIAL_POSITION as u16;
+            let mut arguments = args[1..].iter();
+            while let Some(argument) = arguments.next() {
+                match argument.as_str() {
+                    "--load-address" => {
+                        load_address = arguments
+                            .next()
+                            .and_then(|address| parse_number(address))
+                            .ok_or("--load-address needs a hexadecimal address")?
+                    }
+                    _ if argument == "-" || !argument.starts_with('-') => {
+                        if rom_name.is_some() {
+                            return Err(format!("Unexpected argument {argument}, see --help"));
+                        }
+                        rom_name = Some(argument.clone());
+                    }
+                    _ => return Err(format!("Unknown option {argument}, see --help")),
+                }
+            }
+            Ok(Command::Disassemble {
+                rom_name: rom_name.ok_or("disasm needs a ROM name")?,
+                load_address,
+            })
+        }
+        "asm" => {
+            let (source_path, output_path) = match &args[1..] {
+                [source_path] => (
+                    PathBuf::from(source_path),
+                    Path::new(source_path).with_extension("ch8"),
+                ),
+                [source_path, output_path] => {
+                    (PathBuf::from(source_path), PathBuf::from(output_path))
+                }
+                _ => return Err(String::from("asm needs a source file")),
+            };
+            Ok(Command::Assemble {
+                source_path,
+                output_path,
+            })
+        }
+        "test" => {
+            let (rom_name, settings, expected_path) = parse_run(&args[1..], true)?;
+            Ok(Command::Test {
+                rom_name,
+                settings,
+                expected_path,
+            })
+        }
+        "bench" => {
+            let (rom_name, settings, _) = parse_run(&args[1..], false)?;
+            Ok(Command::Bench { rom_name, settings })
+        }
+        "run" => {
+            let (rom_name, settings, _) = parse_run(&args[1..], false)?;
+            Ok(Command::Run { rom_name, settings })
+        }
+        _ => {
+            let (rom_name, settings, _) = parse_run(args, false)?;
+            Ok(Command::Run { rom_name, settings })
+        }
+    }
+}
+
+/// Reads the ROM name and the options of the commands that run a ROM. Only
+/// `test` compares screens, so only it takes `--expect`.
+fn parse_run(
+    args: &[String],
+    is_test: bool,
+) -> Result<(String, Box<Settings>, Option<PathBuf>), String> {
+    let mut rom_name = None;
+    let mut expected_path = None;
+    let mut config = None;
+    let mut settings = Box::<Settings>::default();
+    let mut arguments = args.iter();
+    while let Some(argument) = arguments.next() {
+        if argument == "-" || !argument.starts_with('-') {
+            if rom_name.is_some() {
+                return Err(format!("Unexpected argument {argument}, see --help"));
+            }
+            rom_name = Some(argument.clone());
+            continue;
+        }
+        match argument.as_str() {
+            "--headless" | "--frames" => {
+                match arguments
+                    .next()
+                    .and_then(|frames| frames.parse::<u32>().ok())
+                {
+                    Some(frames) => settings.frames = Some(frames),
+                    None => return Err(format!("{argument} needs a number of frames")),
+                }
+            }
+            "--quirks" => settings.quirks = Some(parse_value(arguments.next(), argument)?),
+            "--platform" => settings.platform = Some(parse_value(arguments.next(), argument)?),
+            "--colors" | "--palette" => {
+                settings.palette = Some(parse_value::<Palette>(arguments.next(), argument)?)
+            }
+            "--keymap" => {
+                let text = arguments
+                    .next()
+                    .ok_or("--keymap needs a list of KEY=NAME bindings")?;
+                settings.key_bindings.extend(keymap::parse_bindings(text)?);
+            }
+            "--scale" => match arguments.next().and_then(|scale| scale.parse::<u32>().ok()) {
+                Some(scale) if scale > 0 => settings.scale = Some(scale),
+                _ => return Err(String::from("--scale needs a number of pixels")),
+            },
+            "--fullscreen" => settings.is_fullscreen = true,
+            "--volume" => match arguments
+                .next()
+                .and_then(|volume| volume.parse::<u32>().ok())
+            {
+                Some(volume) if volume <= 100 => settings.volume = Some(volume as f32 / 100.0),
+                _ => return Err(String::from("--volume needs a number from 0 to 100")),
+            },
+            "--debug" => {
+                settings.debugger.get_or_insert_with(Debugger::new);
+            }
+            "--break" => match arguments.next().and_then(|address| parse_number(address)) {
+                Some(address) => settings
+                    .debugger
+                    .get_or_insert_with(Debugger::new)
+                    .add_breakpoint(address),
+                None => return Err(String::from("--break needs a hexadecimal address")),
+            },
+            "--seed" => settings.seed = Some(parse_value(arguments.next(), argument)?),
+            "--screenshot" => {
+                settings.screenshot_path = Some(parse_path(arguments.next(), argument)?)
+            }
+            "--expect" if is_test => expected_path = Some(parse_path(arguments.next(), argument)?),
+            "--expect" => return Err(String::from("--expect only works with test")),
+            "--record" => settings.record_path = Some(parse_path(arguments.next(), argument)?),
+            "--replay" => {
+                let path = parse_path(arguments.next(), argument)?;
+                settings.replay = Some(Movie::load(&path)?);
+            }
+            "--config" => {
+                let path = parse_path(arguments.next(), argument)?;
+                config = Some(Config::load(&path)?);
+            }
+            "--ipf" => match arguments.next().and_then(|count| count.parse::<u32>().ok()) {
+                Some(count) if count > 0 => settings.instructions_per_frame = Some(count),
+                _ => {
+                    return Err(String::from(
+                        "--ipf needs a number of instructions per frame",
+                    ))
+                }
+            },
+            "--timing" => {
+                settings.timing = Some(parse_value::<Timing>(arguments.next(), argument)?)
+            }
+            "--speed" => match arguments.next().and_then(|speed| speed.parse::<u32>().ok()) {
+                Some(speed) if speed > 0 => {
+                    settings.instructions_per_frame = Some(clock::instructions_per_frame(speed))
+                }
+                _ => {
+                    return Err(String::from(
+                        "--speed needs a number of instructions per second",
+                    ))
+                }
+            },
+            "--on-error" => {
+                settings.error_policy = parse_value::<ErrorPolicy>(arguments.next(), argument)?
+            }
+            "--stack-depth" => match arguments
+                .next()
+                .and_then(|depth| depth.parse::<usize>().ok())
+            {
+                Some(depth) if depth > 0 => settings.stack_depth = Some(depth),
+                _ => return Err(String::from("--stack-depth needs a number of levels")),
+            },
+            "--vip-stack" => settings.has_memory_stack = true,
+            "--load-address" => match arguments.next().and_then(|address| parse_number(address)) {
+                Some(address) => settings.load_address = Some(address),
+                None => return Err(String::from("--load-address needs a hexadecimal address")),
+            },
+            "--rewind" => match arguments
+                .next()
+                .and_then(|seconds| seconds.parse::<usize>().ok())
+            {
+                Some(seconds) => settings.rewind_seconds = Some(seconds),
+                None => return Err(String::from("--rewind needs a number of seconds")),
+            },
+            _ => return Err(format!("Unknown option {argument}, see --help")),
+        }
+    }
+    let rom_name = rom_name.ok_or("Missing ROM name, see --help")?;
+    settings.config = match config {
+        Some(config) => config,
+        None => Config::load_default()?,
+    };
+    if settings.error_policy == ErrorPolicy::Debug {
+        // Only stop once something goes wrong, unless --debug asked otherwise
+        settings.debugger.get_or_insert_with(|| {
+            let mut debugger = Debugger::new();
+            debugger.resume_running();
+            debugger
+        });
+    }
+    Ok((rom_name, settings, expected_path))
+}
+
+/// Parses the value following `option` with its [`FromStr`] implementation.
+///
+/// [`FromStr`]: std::str::FromStr
+fn parse_value<T>(value: Option<&String>, option: &str) -> Result<T, String>
+where
+    T: std::str::FromStr,
+    T::Err: ToString,
+{
+    let value = value.ok_or_else(|| format!("{option} needs a value"))?;
+    value
+        .parse()
+        .map_err(|error: T::Err| format!("{option}: {}", error.to_string()))
+}
+
+fn parse_path(value: Option<&String>, option: &str) -> Result<PathBuf, String> {
+    value
+        .map(PathBuf::from)
+        .ok_or_else(|| format!("{option} needs a file name"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Command, String> {
+        let args: Vec<String> = args.split_whitespace().map(String::from).collect();
+        parse_command(&args)
+    }
+
+    #[test]
+    fn dispatches_subcommands() {
+        assert!(matches!(parse(""), Ok(Command::Help)));
+        assert!(matches!(parse("help"), Ok(Command::Help)));
+        assert!(matches!(parse("run pong.ch8 --help"), Ok(Command::Help)));
+        assert!(matches!(
+            parse("run pong.ch8"),
+            Ok(Command::Run { rom_name, .. }) if rom_name == "pong.ch8"
+        ));
+        assert!(matches!(
+            parse("test pong.ch8 --expect pong.txt"),
+            Ok(Command::Test { expected_path: Some(path), .. }) if path == Path::new("pong.txt")
+        ));
+        assert!(matches!(parse("bench pong.ch8"), Ok(Command::Bench { .. })));
+        assert!(matches!(
+            parse("disasm pong.ch8 --load-address 600"),
+            Ok(Command::Disassemble {
+                load_address: 0x600,
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse("asm pong.8o"),
+            Ok(Command::Assemble { output_path, .. }) if output_path == Path::new("pong.ch8")
+        ));
+    }
+
+    #[test]
+    fn runs_a_bare_rom_name_or_stdin() {
+        assert!(matches!(
+            parse("pong.ch8 --ipf 20"),
+            Ok(Command::Run { rom_name, settings })
+                if rom_name == "pong.ch8" && settings.instructions_per_frame == Some(20)
+        ));
+        assert!(matches!(
+            parse("-"),
+            Ok(Command::Run { rom_name, .. }) if rom_name == "-"
+        ));
+    }
+
+    #[test]
+    fn takes_expect_only_for_test() {
+        for command in ["run", "bench"] {
+            assert_eq!(
+                parse(&format!("{command} pong.ch8 --expect pong.txt")).err(),
+                Some(String::from("--expect only works with test"))
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_options_without_their_value() {
+        let options = [
+            "--headless",
+            "--frames",
+            "--quirks",
+            "--platform",
+            "--colors",
+            "--palette",
+            "--keymap",
+            "--scale",
+            "--volume",
+            "--break",
+            "--seed",
+            "--screenshot",
+            "--expect",
+            "--record",
+            "--replay",
+            "--config",
+            "--ipf",
+            "--timing",
+            "--speed",
+            "--on-error",
+            "--stack-depth",
+            "--load-address",
+            "--rewind",
+        ];
+        for option in options {
+            let error = parse(&format!("test pong.ch8 {option}")).err();
+            assert!(
+                error
+                    .as_ref()
+                    .is_some_and(|error| error.starts_with(option)),
+                "{option}: {error:?}"
+            );
+        }
+        assert!(parse("disasm pong.ch8 --load-address").is_err());
+        assert!(parse("disasm").is_err());
+        assert!(parse("asm").is_err());
+        assert!(parse("run").is_err());
+        assert!(parse("run pong.ch8 --unknown").is_err());
+    }
+}
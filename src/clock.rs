@@ -0,0 +1,57 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The timers count down and the screen refreshes at 60 Hz.
+pub const FRAMES_PER_SECOND: u32 = 60;
+/// Roughly the 700 instructions per second most ROMs are written for.
+pub const DEFAULT_INSTRUCTIONS_PER_FRAME: u32 = instructions_per_frame(700);
+/// How far behind the clock may fall before it gives up catching up, e.g.
+/// after the window was dragged or the process stopped.
+const MAX_LAG_FRAMES: u32 = 5;
+
+/// Converts a speed in instructions per second to the nearest whole number of
+/// instructions per frame, at least one.
+pub const fn instructions_per_frame(speed: u32) -> u32 {
+    let instructions_per_frame = (speed + FRAMES_PER_SECOND / 2) / FRAMES_PER_SECOND;
+    if instructions_per_frame > 0 {
+        instructions_per_frame
+    } else {
+        1
+    }
+}
+
+/// Paces a loop to a fixed frame rate. Every deadline is one period after the
+/// previous one rather than after the moment the wait ended, so the time
+/// spent oversleeping is taken back on the next frame and the rate doesn't
+/// drift.
+pub struct FrameClock {
+    period: Duration,
+    deadline: Instant,
+}
+
+impl Default for FrameClock {
+    fn default() -> Self {
+        Self::new(FRAMES_PER_SECOND)
+    }
+}
+
+impl FrameClock {
+    pub fn new(frames_per_second: u32) -> Self {
+        let period = Duration::from_secs(1) / frames_per_second.max(1);
+        Self {
+            period,
+            deadline: Instant::now() + period,
+        }
+    }
+
+    /// Sleeps until the end of the current frame.
+    pub fn wait(&mut self) {
+        let now = Instant::now();
+        if now < self.deadline {
+            thread::sleep(self.deadline - now);
+        } else if now - self.deadline > self.period * MAX_LAG_FRAMES {
+            self.deadline = now;
+        }
+        self.deadline += self.period;
+    }
+}
@@ -0,0 +1,421 @@
+use crate::clock;
+use crate::display::Palette;
+use crate::keymap::{self, Keymap};
+use crate::platform::Platform;
+use crate::quirks::Quirks;
+use crate::scheduler::Timing;
+use crate::sha1;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use toml::{Table, Value};
+
+/// Settings read from a TOML file, by default the one at
+/// [`Config::default_path`]. Every section is optional:
+///
+/// ```toml
+/// # Instructions run per 60 Hz frame, or "speed" per second
+/// ipf = 11
+/// # "ipf", or "vip" for the speed of the COSMAC VIP
+/// timing = "ipf"
+/// # Names as for --platform and --quirks
+/// platform = "chip8"
+/// quirks = "vip"
+/// # Background first, as for --colors
+/// colors = "000000,FFFFFF"
+///
+/// # Listing a CHIP-8 key replaces its default bindings
+/// [keys]
+/// 5 = ["W", "Up"]
+/// 8 = ["S", "Down"]
+///
+/// # Game controller buttons, and stick directions as axis+ or axis-
+/// [controller]
+/// deadzone = 0.3
+/// [controller.keys]
+/// 5 = ["dpup", "lefty-"]
+///
+/// # Applied on top of the settings above when running pong.ch8
+/// [roms."pong.ch8"]
+/// timing = "vip"
+/// [roms."pong.ch8".keys]
+/// 1 = ["Up"]
+/// 4 = ["Down"]
+///
+/// # Or for the ROM with this SHA-1, whatever its name, as `sha1sum` shows it
+/// [roms.3b2a7b7e0b8d4a1e5c6f9d0e1a2b3c4d5e6f7a8b]
+/// platform = "schip"
+/// speed = 1200
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    defaults: Profile,
+    /// Settings for single ROMs, by lowercase SHA-1 or by file name.
+    roms: HashMap<String, Profile>,
+}
+
+/// A ROM as the config looks it up: by the SHA-1 of its bytes first, then by
+/// file name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RomId {
+    hash: String,
+    file_name: Option<String>,
+}
+
+impl RomId {
+    pub fn new(rom_name: &str, rom_data: &[u8]) -> Self {
+        Self {
+            hash: sha1::to_hex(&sha1::sha1(rom_data)),
+            file_name: Path::new(rom_name)
+                .file_name()
+                .and_then(|file_name| file_name.to_str())
+                .map(String::from),
+        }
+    }
+
+    pub fn get_hash(&self) -> &str {
+        &self.hash
+    }
+}
+
+/// The settings that can be given for every ROM or for a single one.
+#[derive(Clone, Debug, Default)]
+struct Profile {
+    keys: Vec<(u8, Vec<String>)>,
+    controller_keys: Vec<(u8, Vec<String>)>,
+    deadzone: Option<f32>,
+    instructions_per_frame: Option<u32>,
+    timing: Option<Timing>,
+    platform: Option<Platform>,
+    quirks: Option<Quirks>,
+    palette: Option<Palette>,
+    seed: Option<u64>,
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let table = text.parse::<Table>().map_err(|error| error.to_string())?;
+        let mut config = Self::default();
+        for (name, value) in &table {
+            if name == "roms" {
+                let roms = value
+                    .as_table()
+                    .ok_or("roms must be a table of ROM names")?;
+                for (rom_name, value) in roms {
+                    let section = format!("roms.\"{rom_name}\"");
+                    let table = value
+                        .as_table()
+                        .ok_or_else(|| format!("{section} must be a table"))?;
+                    let mut profile = Profile::default();
+                    for (name, value) in table {
+                        profile.parse_setting(name, value, &format!("{section}.{name}"))?;
+                    }
+                    config.roms.insert(normalize_rom_name(rom_name), profile);
+                }
+            } else {
+                config.defaults.parse_setting(name, value, name)?;
+            }
+        }
+        Ok(config)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|error| format!("Can't read {}: {error}", path.display()))?;
+        Self::parse(&text).map_err(|error| format!("{}: {error}", path.display()))
+    }
+
+    /// `chip-8/config.toml` in `$XDG_CONFIG_HOME`, or in `~/.config` when that
+    /// isn't set.
+    pub fn default_path() -> Option<PathBuf> {
+        let config_home = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+        Some(config_home.join("chip-8").join("config.toml"))
+    }
+
+    /// Loads the file at [`Config::default_path`], or an empty config when
+    /// there is none.
+    pub fn load_default() -> Result<Self, String> {
+        match Self::default_path() {
+            Some(path) if path.exists() => Self::load(&path),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    /// The default layout with the bindings of the config, then those of the
+    /// ROM, on top.
+    pub fn get_keymap(&self, rom: &RomId) -> Keymap {
+        let mut keymap = Keymap::default();
+        self.defaults.apply(&mut keymap);
+        let profiles: Vec<&Profile> = self.get_rom_profiles(rom).collect();
+        for profile in profiles.into_iter().rev() {
+            profile.apply(&mut keymap);
+        }
+        keymap
+    }
+
+    /// The ROM's speed, or the default one when it has none.
+    pub fn get_instructions_per_frame(&self, rom: &RomId) -> Option<u32> {
+        self.get_setting(rom, |profile| profile.instructions_per_frame)
+    }
+
+    pub fn get_timing(&self, rom: &RomId) -> Option<Timing> {
+        self.get_setting(rom, |profile| profile.timing)
+    }
+
+    pub fn get_platform(&self, rom: &RomId) -> Option<Platform> {
+        self.get_setting(rom, |profile| profile.platform)
+    }
+
+    pub fn get_quirks(&self, rom: &RomId) -> Option<Quirks> {
+        self.get_setting(rom, |profile| profile.quirks)
+    }
+
+    pub fn get_palette(&self, rom: &RomId) -> Option<Palette> {
+        self.get_setting(rom, |profile| profile.palette)
+    }
+
+    pub fn get_seed(&self, rom: &RomId) -> Option<u64> {
+        self.get_setting(rom, |profile| profile.seed)
+    }
+
+    /// A setting of the ROM's profiles, or the default one when they don't
+    /// have it.
+    fn get_setting<T>(&self, rom: &RomId, setting: impl Fn(&Profile) -> Option<T>) -> Option<T> {
+        self.get_rom_profiles(rom)
+            .find_map(&setting)
+            .or_else(|| setting(&self.defaults))
+    }
+
+    /// The profiles of the ROM, most specific first: the one for its hash,
+    /// then the one for its file name, which applies wherever the ROM is.
+    fn get_rom_profiles<'a>(&'a self, rom: &'a RomId) -> impl Iterator<Item = &'a Profile> {
+        let hash_profile = self.roms.get(&rom.hash);
+        let name_profile = rom
+            .file_name
+            .as_ref()
+            .and_then(|file_name| self.roms.get(file_name));
+        hash_profile.into_iter().chain(name_profile)
+    }
+}
+
+/// Hashes are matched whatever their case.
+fn normalize_rom_name(rom_name: &str) -> String {
+    let is_hash = rom_name.len() == 40 && rom_name.chars().all(|c| c.is_ascii_hexdigit());
+    if is_hash {
+        rom_name.to_ascii_lowercase()
+    } else {
+        rom_name.to_string()
+    }
+}
+
+impl Profile {
+    fn parse_setting(&mut self, name: &str, value: &Value, section: &str) -> Result<(), String> {
+        let is_speed = name == "ipf" || name == "speed";
+        if is_speed && self.instructions_per_frame.is_some() {
+            return Err(format!("{section}: set either ipf or speed, not both"));
+        }
+        match name {
+            "keys" => self.keys = parse_keys(value, section)?,
+            "ipf" => {
+                let instructions_per_frame = value
+                    .as_integer()
+                    .and_then(|count| u32::try_from(count).ok())
+                    .filter(|count| *count > 0)
+                    .ok_or_else(|| format!("{section} must be a positive number"))?;
+                self.instructions_per_frame = Some(instructions_per_frame);
+            }
+            "speed" => {
+                let speed = value
+                    .as_integer()
+                    .and_then(|speed| u32::try_from(speed).ok())
+                    .filter(|speed| *speed > 0)
+                    .ok_or_else(|| format!("{section} must be a positive number"))?;
+                self.instructions_per_frame = Some(clock::instructions_per_frame(speed));
+            }
+            "timing" => self.timing = Some(parse_name(value, section)?),
+            "platform" => self.platform = Some(parse_name(value, section)?),
+            "quirks" => self.quirks = Some(parse_name(value, section)?),
+            "colors" => self.palette = Some(parse_name(value, section)?),
+            "seed" => {
+                let seed = value
+                    .as_integer()
+                    .and_then(|seed| u64::try_from(seed).ok())
+                    .ok_or_else(|| format!("{section} must be a non-negative number"))?;
+                self.seed = Some(seed);
+            }
+            "controller" => {
+                let table = value
+                    .as_table()
+                    .ok_or_else(|| format!("{section} must be a table"))?;
+                for (name, value) in table {
+                    let section = format!("{section}.{name}");
+                    match name.as_str() {
+                        "keys" => self.controller_keys = parse_keys(value, &section)?,
+                        "deadzone" => self.deadzone = Some(parse_deadzone(value, &section)?),
+                        _ => return Err(format!("Unknown setting {section}")),
+                    }
+                }
+            }
+            _ => return Err(format!("Unknown setting {section}")),
+        }
+        Ok(())
+    }
+
+    fn apply(&self, keymap: &mut Keymap) {
+        for (key, names) in &self.keys {
+            keymap
+                .set_bindings(*key, names.clone())
+                .expect("keys are checked when parsing");
+        }
+        for (key, names) in &self.controller_keys {
+            keymap
+                .set_controller_bindings(*key, names.clone())
+                .expect("keys are checked when parsing");
+        }
+        if let Some(deadzone) = self.deadzone {
+            keymap.set_deadzone(deadzone);
+        }
+    }
+}
+
+/// Reads a table of CHIP-8 keys, each bound to a key name or a list of them.
+fn parse_keys(value: &Value, section: &str) -> Result<Vec<(u8, Vec<String>)>, String> {
+    let table = value
+        .as_table()
+        .ok_or_else(|| format!("{section} must be a table"))?;
+    table
+        .iter()
+        .map(|(key, value)| {
+            let key = keymap::parse_key(key)
+                .ok_or_else(|| format!("{section}: {key} is not a CHIP-8 key, use 0 to F"))?;
+            Ok((key, parse_names(value, section)?))
+        })
+        .collect()
+}
+
+fn parse_names(value: &Value, section: &str) -> Result<Vec<String>, String> {
+    match value {
+        Value::String(name) => Ok(vec![name.clone()]),
+        Value::Array(values) => values
+            .iter()
+            .map(|value| {
+                value
+                    .as_str()
+                    .map(String::from)
+                    .ok_or_else(|| format!("{section}: key names must be strings"))
+            })
+            .collect(),
+        _ => Err(format!("{section}: expected a key name or a list of them")),
+    }
+}
+
+/// Reads a string setting with its [`FromStr`] implementation.
+fn parse_name<T: FromStr<Err = String>>(value: &Value, section: &str) -> Result<T, String> {
+    let name = value
+        .as_str()
+        .ok_or_else(|| format!("{section} must be a string"))?;
+    name.parse().map_err(|error| format!("{section}: {error}"))
+}
+
+fn parse_deadzone(value: &Value, section: &str) -> Result<f32, String> {
+    let deadzone = match value {
+        Value::Float(deadzone) => *deadzone,
+        Value::Integer(deadzone) => *deadzone as f64,
+        _ => return Err(format!("{section} must be a number")),
+    };
+    if (0.0..1.0).contains(&deadzone) {
+        Ok(deadzone as f32)
+    } else {
+        Err(format!("{section} must be at least 0 and less than 1"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROM: [u8; 2] = [0x12, 0x00];
+
+    fn rom_hash() -> String {
+        sha1::to_hex(&sha1::sha1(&ROM))
+    }
+
+    #[test]
+    fn prefers_the_hash_profile_then_the_name_profile_then_the_defaults() {
+        let config = Config::parse(&format!(
+            "ipf = 5\n\
+             quirks = \"vip\"\n\
+             [roms.\"pong.ch8\"]\n\
+             ipf = 6\n\
+             timing = \"vip\"\n\
+             [roms.{}]\n\
+             ipf = 7\n",
+            rom_hash().to_uppercase()
+        ))
+        .unwrap();
+        let pong = RomId::new("roms/pong.ch8", &ROM);
+        assert_eq!(config.get_instructions_per_frame(&pong), Some(7));
+        assert_eq!(config.get_timing(&pong), Some(Timing::VipCycles));
+        assert_eq!(config.get_quirks(&pong), Some(Quirks::cosmac_vip()));
+        let renamed = RomId::new("copy.ch8", &ROM);
+        assert_eq!(config.get_instructions_per_frame(&renamed), Some(7));
+        assert_eq!(config.get_timing(&renamed), None);
+        let other = RomId::new("pong.ch8", &[0x00, 0xE0]);
+        assert_eq!(config.get_instructions_per_frame(&other), Some(6));
+        let unknown = RomId::new("other.ch8", &[0x00, 0xE0]);
+        assert_eq!(config.get_instructions_per_frame(&unknown), Some(5));
+    }
+
+    #[test]
+    fn rejects_unknown_settings() {
+        assert_eq!(
+            Config::parse("volume = 3").unwrap_err(),
+            "Unknown setting volume"
+        );
+        assert_eq!(
+            Config::parse("[roms.\"pong.ch8\"]\nvolume = 3").unwrap_err(),
+            "Unknown setting roms.\"pong.ch8\".volume"
+        );
+        assert_eq!(
+            Config::parse("[controller]\nkeys = {}\nspeed = 3").unwrap_err(),
+            "Unknown setting controller.speed"
+        );
+    }
+
+    #[test]
+    fn rejects_both_ipf_and_speed() {
+        assert!(Config::parse("ipf = 10\nspeed = 700").is_err());
+        assert!(Config::parse("[roms.\"pong.ch8\"]\nspeed = 700\nipf = 10").is_err());
+        let config = Config::parse("ipf = 10\n[roms.\"pong.ch8\"]\nspeed = 700").unwrap();
+        let pong = RomId::new("pong.ch8", &ROM);
+        assert_eq!(config.get_instructions_per_frame(&pong), Some(12));
+    }
+
+    #[test]
+    fn merges_the_rom_bindings_into_the_default_ones() {
+        let config = Config::parse(
+            "[keys]\n\
+             5 = [\"W\"]\n\
+             [controller.keys]\n\
+             5 = \"dpup\"\n\
+             [roms.\"pong.ch8\".keys]\n\
+             5 = [\"Up\"]\n\
+             8 = \"S\"\n",
+        )
+        .unwrap();
+        let default_keymap = Keymap::default();
+        let pong = config.get_keymap(&RomId::new("pong.ch8", &ROM));
+        assert_eq!(pong.get_bindings(5), ["Up"]);
+        assert_eq!(pong.get_bindings(8), ["S"]);
+        assert_eq!(pong.get_bindings(4), default_keymap.get_bindings(4));
+        assert_eq!(pong.get_controller_bindings(5), ["dpup"]);
+        let other = config.get_keymap(&RomId::new("other.ch8", &ROM));
+        assert_eq!(other.get_bindings(5), ["W"]);
+        assert_eq!(other.get_bindings(8), default_keymap.get_bindings(8));
+    }
+}
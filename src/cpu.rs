@@ -1,174 +1,265 @@
+mod cycles;
+mod key_wait;
 mod memory;
+mod random;
 mod registers;
+mod snapshot;
+mod stack;
 
-use crate::display::DisplayChip8;
-use memory::Memory;
-use rand::{self, Rng};
-use registers::Registers;
-use sdl2::audio::AudioCallback;
-use sdl2::audio::AudioSpecDesired;
-use sdl2::event::Event;
-use sdl2::keyboard::Scancode;
-use std::time::Instant;
-
-const SEC_TO_NANOS: u128 = 1_000_000_000;
-const SCANCODES_KEYS: [Scancode; 16] = [
-    Scancode::Num1,
-    Scancode::Num2,
-    Scancode::Num3,
-    Scancode::Num4,
-    Scancode::Q,
-    Scancode::W,
-    Scancode::E,
-    Scancode::R,
-    Scancode::A,
-    Scancode::S,
-    Scancode::D,
-    Scancode::F,
-    Scancode::Z,
-    Scancode::X,
-    Scancode::C,
-    Scancode::V,
-];
-
-fn get_scancode_key(scancode: Scancode) -> Option<u8> {
-    match scancode {
-        Scancode::Num1 => Some(1),
-        Scancode::Num2 => Some(2),
-        Scancode::Num3 => Some(3),
-        Scancode::Num4 => Some(0xC),
-        Scancode::Q => Some(4),
-        Scancode::W => Some(5),
-        Scancode::E => Some(6),
-        Scancode::R => Some(0xD),
-        Scancode::A => Some(7),
-        Scancode::S => Some(8),
-        Scancode::D => Some(9),
-        Scancode::F => Some(0xE),
-        Scancode::Z => Some(0xA),
-        Scancode::X => Some(0),
-        Scancode::C => Some(0xB),
-        Scancode::V => Some(0xF),
-        _ => None,
-    }
-}
+pub use key_wait::KeyWait;
+pub use memory::{AccessKind, Memory, MemoryAccess, Watch, INITIAL_POSITION};
+pub use random::Random;
+pub use registers::Registers;
+pub use snapshot::{Snapshot, SNAPSHOT_VERSION};
+pub use stack::{Stack, DEFAULT_STACK_DEPTH, VIP_STACK_ADDRESS};
+
+use crate::display::{
+    Display, Framebuffer, HeadlessDisplay, HEIGHT_PIXEL_COUNT, HIRES_HEIGHT_PIXEL_COUNT,
+    HIRES_WIDTH_PIXEL_COUNT, WIDTH_PIXEL_COUNT,
+};
+use crate::error::Chip8Error;
+use crate::instruction::Instruction;
+use crate::platform::Platform;
+use crate::quirks::Quirks;
+
+const DEFAULT_PITCH: u8 = 64;
 
-pub struct CPU {
+#[allow(clippy::upper_case_acronyms)]
+pub struct CPU<D: Display> {
     registers: Registers,
     memory: Memory,
-    display: DisplayChip8,
-    stack: Vec<u16>,
+    display: D,
+    stack: Stack,
     keys: [bool; 16],
     delay_timer: u8,
     sound_timer: u8,
+    quirks: Quirks,
+    platform: Platform,
+    rpl_flags: [u8; 16],
+    audio_pattern: Option<[u8; 16]>,
+    pitch: u8,
+    random: Random,
+    is_waiting_for_frame: bool,
+    is_halted: bool,
+    key_wait: Option<KeyWait>,
+    /// COSMAC VIP machine cycles spent so far.
+    cycles: u64,
 }
 
-struct SquareWave {
-    phase_inc: f32,
-    phase: f32,
-    volume: f32,
+impl CPU<HeadlessDisplay> {
+    pub fn new() -> Self {
+        Self::with_display(HeadlessDisplay::new())
+    }
 }
 
-impl AudioCallback for SquareWave {
-    type Channel = f32;
-    fn callback(&mut self, out: &mut [f32]) {
-        //Generate a square wave
-        for x in out.iter_mut() {
-            *x = if self.phase <= 0.5 {
-                self.volume
-            } else {
-                -self.volume
-            };
-            self.phase = (self.phase + self.phase_inc) % 1.0;
-        }
+impl Default for CPU<HeadlessDisplay> {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-impl CPU {
-    pub fn new(pixel_size: u32) -> Self {
+impl<D: Display> CPU<D> {
+    pub fn with_display(display: D) -> Self {
         Self {
             registers: Registers::default(),
             memory: Memory::default(),
-            display: DisplayChip8::new(pixel_size),
-            stack: Vec::new(),
+            display,
+            stack: Stack::default(),
             keys: [false; 16],
             delay_timer: 0,
             sound_timer: 0,
+            quirks: Quirks::default(),
+            platform: Platform::default(),
+            rpl_flags: [0; 16],
+            audio_pattern: None,
+            pitch: DEFAULT_PITCH,
+            random: Random::default(),
+            is_waiting_for_frame: false,
+            is_halted: false,
+            key_wait: None,
+            cycles: 0,
         }
     }
 
-    pub fn run(&mut self) {
-        let sdl_context = self.display.canvas.window().subsystem().sdl();
-        let audio_subsystem = sdl_context.audio().unwrap();
-        let desired_spec = AudioSpecDesired {
-            freq: Some(44100),
-            channels: Some(1), // Mono
-            samples: None,     // default sample size
-        };
+    pub fn display(&self) -> &D {
+        &self.display
+    }
 
-        let device = audio_subsystem
-            .open_playback(None, &desired_spec, |spec| {
-                // initialize the audio callback
-                SquareWave {
-                    phase_inc: 440.0 / spec.freq as f32,
-                    phase: 0.0,
-                    volume: 0.1,
-                }
-            })
-            .unwrap();
-
-        let mut events = sdl_context.event_pump().unwrap();
-        let mut cpu_tick_acc = 0;
-        let cpu_ticks_per_second = 700;
-        let mut timer_ticks = 0;
-        let timer_ticks_per_second = 60;
-        let mut delta_time = 0;
-        let mut is_audio_playing = false;
-        'gameloop: loop {
-            let begin = Instant::now();
-            cpu_tick_acc += delta_time;
-            timer_ticks += delta_time;
-            if cpu_tick_acc > (SEC_TO_NANOS / cpu_ticks_per_second) {
-                self.tick();
-                cpu_tick_acc = 0;
-            }
-            if timer_ticks > (SEC_TO_NANOS / timer_ticks_per_second) {
-                self.tick_timers();
-                timer_ticks = 0;
-            }
-            if self.sound_timer == 0 {
-                device.pause();
-                is_audio_playing = false;
-            } else if !is_audio_playing {
-                is_audio_playing = true;
-                device.resume();
-            }
-            let keyboard_state = events.keyboard_state();
-            for scancode in SCANCODES_KEYS {
-                if let Some(key) = get_scancode_key(scancode) {
-                    self.keys[key as usize] = keyboard_state.is_scancode_pressed(scancode);
-                }
-            }
-            for event in events.poll_iter() {
-                match event {
-                    Event::Quit { .. } => {
-                        break 'gameloop;
-                    }
-                    _ => (),
-                }
-            }
+    pub fn display_mut(&mut self) -> &mut D {
+        &mut self.display
+    }
+
+    pub fn framebuffer(&self) -> &Framebuffer {
+        self.display.framebuffer()
+    }
+
+    pub fn registers(&self) -> &Registers {
+        &self.registers
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    /// Reports future reads and/or writes of `address` through
+    /// [`CPU::take_memory_accesses`].
+    pub fn add_watchpoint(&mut self, address: u16, watch: Watch) {
+        self.memory.add_watchpoint(address, watch);
+    }
+
+    pub fn remove_watchpoint(&mut self, address: u16) -> bool {
+        self.memory.remove_watchpoint(address)
+    }
+
+    pub fn take_memory_accesses(&self) -> Vec<MemoryAccess> {
+        self.memory.take_accesses()
+    }
+
+    pub fn stack(&self) -> &[u16] {
+        self.stack.entries()
+    }
+
+    pub fn get_stack_depth(&self) -> usize {
+        self.stack.get_depth()
+    }
 
-            let end = Instant::now();
-            delta_time = end.duration_since(begin).as_nanos();
+    /// Sets how many subroutine calls can be nested before 2NNN fails with
+    /// [`Chip8Error::StackOverflow`].
+    pub fn set_stack_depth(&mut self, depth: usize) {
+        self.stack.set_depth(depth);
+    }
+
+    pub fn get_stack_address(&self) -> Option<u16> {
+        self.stack.get_address()
+    }
+
+    /// Keeps the stack in emulated memory from `address` on, e.g.
+    /// [`VIP_STACK_ADDRESS`], or outside of it when `None`.
+    pub fn set_stack_address(&mut self, address: Option<u16>) {
+        self.stack.set_address(address);
+    }
+
+    pub fn keys(&self) -> &[bool; 16] {
+        &self.keys
+    }
+
+    pub fn quirks(&self) -> &Quirks {
+        &self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    /// Selects the instruction set and resizes memory to match it. Call this
+    /// before loading a ROM.
+    pub fn set_platform(&mut self, platform: Platform) {
+        self.platform = platform;
+        self.memory.resize(platform.memory_size());
+    }
+
+    /// Makes CXNN produce the same numbers on every run with this seed.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.random = Random::new(seed);
+    }
+
+    /// Whether the program ended with the SUPER-CHIP exit instruction (00FD).
+    pub fn is_halted(&self) -> bool {
+        self.is_halted
+    }
+
+    /// The XO-CHIP 128 bit sample loaded by F002, if the program set one.
+    /// Without a pattern the buzzer plays a plain tone.
+    pub fn audio_pattern(&self) -> Option<&[u8; 16]> {
+        self.audio_pattern.as_ref()
+    }
+
+    /// Rate in bits per second at which the audio pattern is played, as set
+    /// by FX3A.
+    pub fn get_pattern_playback_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0)
+    }
+
+    pub fn get_delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn get_sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    pub fn set_key(&mut self, key: u8, is_pressed: bool) {
+        if let Some(key_state) = self.keys.get_mut(key as usize) {
+            *key_state = is_pressed;
         }
+        self.update_key_wait();
     }
 
-    fn tick(&mut self) {
-        let instruction = self.fetch_instruction();
-        self.decode_and_execute(instruction);
+    pub fn set_keys(&mut self, keys: [bool; 16]) {
+        self.keys = keys;
+        self.update_key_wait();
     }
 
-    fn tick_timers(&mut self) {
+    /// Whether an FX0A is blocking execution until a key is pressed.
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.key_wait.is_some()
+    }
+
+    /// Fetches, decodes and executes a single instruction.
+    ///
+    /// On an error the program counter is already past the instruction, so
+    /// stepping again carries on with the next one.
+    pub fn step(&mut self) -> Result<(), Chip8Error> {
+        if self.is_waiting() {
+            return Ok(());
+        }
+        let address = self.registers.get_program_counter();
+        self.registers.increase_program_counter(2);
+        let instruction = match self.memory.get_instruction(address) {
+            Ok(instruction) => instruction,
+            Err(error) => {
+                self.cycles += cycles::FETCH_CYCLES as u64;
+                return Err(error);
+            }
+        };
+        self.cycles += cycles::get_vip_cycles(&instruction, &self.registers) as u64;
+        if !instruction.is_supported_on(self.platform) {
+            return Err(Chip8Error::InvalidOpcode {
+                address,
+                opcode: self.memory.get_word(address)?,
+            });
+        }
+        self.execute(instruction, address)?;
+        if instruction.is_skip() && self.registers.get_program_counter() != address.wrapping_add(2)
+        {
+            self.cycles += cycles::SKIP_CYCLES as u64;
+        }
+        Ok(())
+    }
+
+    /// Stops execution for good, as 00FD does.
+    pub fn halt(&mut self) {
+        self.is_halted = true;
+    }
+
+    /// Whether [`CPU::step`] does nothing until the next frame, a key press or
+    /// forever.
+    pub fn is_waiting(&self) -> bool {
+        self.is_waiting_for_frame || self.is_halted || self.key_wait.is_some()
+    }
+
+    /// Machine cycles the instructions run so far would have taken on the
+    /// COSMAC VIP.
+    pub fn get_cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn tick_timers(&mut self) {
+        self.is_waiting_for_frame = false;
         if self.delay_timer > 0 {
             self.delay_timer -= 1;
         }
@@ -177,211 +268,258 @@ impl CPU {
         }
     }
 
-    pub fn load_rom(&mut self, data: &[u8]) {
-        self.memory.load(data);
+    pub fn load_rom(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+        self.load_rom_at(data, INITIAL_POSITION as u16)
     }
 
-    fn fetch_byte(&mut self) -> u8 {
-        let byte = self.memory.get_value(self.registers.get_program_counter());
-        self.registers.increase_program_counter(1);
-        byte
+    /// Loads a ROM at `address` and starts execution there, e.g. 0x600 for
+    /// ETI-660 programs.
+    pub fn load_rom_at(&mut self, data: &[u8], address: u16) -> Result<(), Chip8Error> {
+        self.memory.load(address, data)?;
+        self.registers.set_program_counter(address);
+        Ok(())
     }
 
-    fn fetch_instruction(&mut self) -> u16 {
-        let first_half = self.fetch_byte() as u16;
-        let second_half = self.fetch_byte() as u16;
-        let instruction = (first_half << 8) | second_half;
-        instruction
+    /// Captures the whole machine state.
+    pub fn snapshot(&self) -> Snapshot {
+        let mut registers = [0; 16];
+        for (register, value) in registers.iter_mut().enumerate() {
+            *value = self.registers.get_register(register as u8);
+        }
+        let framebuffer = self.display.framebuffer();
+        Snapshot {
+            platform: self.platform,
+            registers,
+            index: self.registers.get_index(),
+            program_counter: self.registers.get_program_counter(),
+            stack: self.stack.entries().to_vec(),
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            keys: self.keys,
+            rpl_flags: self.rpl_flags,
+            audio_pattern: self.audio_pattern,
+            pitch: self.pitch,
+            random_state: self.random.state(),
+            is_waiting_for_frame: self.is_waiting_for_frame,
+            is_halted: self.is_halted,
+            key_wait: self.key_wait,
+            is_hires: framebuffer.is_hires(),
+            selected_planes: framebuffer.selected_planes(),
+            pixels: framebuffer.pixels().to_vec(),
+            memory: self.memory.bytes().to_vec(),
+        }
     }
-    fn decode_and_execute(&mut self, opcode: u16) {
-        let first_nibble = (opcode & (0xF000)) >> 12;
-        let second_nibble = (opcode & (0x0F00)) >> 8;
-        let third_nibble = (opcode & (0x00F0)) >> 4;
-        let fourth_nibble = (opcode & (0x000F)) >> 0;
-        match (first_nibble, second_nibble, third_nibble, fourth_nibble) {
-            (0x0, 0x0, 0xE, 0x0) => self.display.clear(),
-            (0x0, 0x0, 0xE, 0xE) => self.pop_stack(),
-            (0x1, _, _, _) => {
-                let address = (second_nibble << 8) | (third_nibble << 4) | fourth_nibble;
-                self.registers.set_program_counter(address);
-            }
-            (0x2, _, _, _) => {
-                self.push_stack((second_nibble << 8) | (third_nibble << 4) | fourth_nibble)
-            }
-            (0x3, _, _, _) => self.skip_if_eq_register(
-                second_nibble as u8,
-                ((third_nibble << 4) | fourth_nibble) as u8,
-            ),
-            (0x4, _, _, _) => self.skip_if_not_eq_register(
-                second_nibble as u8,
-                ((third_nibble << 4) | fourth_nibble) as u8,
-            ),
-            (0x5, _, _, 0x0) => self.skip_if_reg_equals(second_nibble as u8, third_nibble as u8),
-            (0x6, _, _, _) => self.registers.set_register(
-                second_nibble as usize,
-                ((third_nibble << 4) | fourth_nibble) as u8,
-            ),
-            (0x7, _, _, _) => self.add_to_register(
-                second_nibble as u8,
-                ((third_nibble << 4) | fourth_nibble) as u8,
-            ),
-            (0x8, _, _, 0x0) => self.set_register_value(second_nibble as u8, third_nibble as u8),
-            (0x8, _, _, 0x1) => {
-                self.or_registers(second_nibble as u8, third_nibble as u8, second_nibble as u8)
-            }
-            (0x8, _, _, 0x2) => {
-                self.and_registers(second_nibble as u8, third_nibble as u8, second_nibble as u8)
-            }
-            (0x8, _, _, 0x3) => {
-                self.xor_registers(second_nibble as u8, third_nibble as u8, second_nibble as u8)
-            }
-            (0x8, _, _, 0x4) => {
-                self.add_registers(second_nibble as u8, third_nibble as u8, second_nibble as u8)
+
+    /// Puts the machine back into a captured state. Nothing changes when the
+    /// snapshot doesn't fit its own platform.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), String> {
+        if snapshot.memory.len() != snapshot.platform.memory_size() {
+            return Err(format!(
+                "Save state has {} bytes of memory, expected {}",
+                snapshot.memory.len(),
+                snapshot.platform.memory_size()
+            ));
+        }
+        let (width, height) = if snapshot.is_hires {
+            (HIRES_WIDTH_PIXEL_COUNT, HIRES_HEIGHT_PIXEL_COUNT)
+        } else {
+            (WIDTH_PIXEL_COUNT, HEIGHT_PIXEL_COUNT)
+        };
+        if snapshot.pixels.len() != width as usize * height as usize {
+            return Err(format!(
+                "Save state has {} pixels, expected {width}x{height}",
+                snapshot.pixels.len()
+            ));
+        }
+        let framebuffer = self.display.framebuffer_mut();
+        framebuffer.set_hires(snapshot.is_hires);
+        framebuffer.set_pixels(&snapshot.pixels)?;
+        framebuffer.select_planes(snapshot.selected_planes);
+        self.display.refresh();
+        self.platform = snapshot.platform;
+        self.memory.restore(&snapshot.memory);
+        for (register, value) in snapshot.registers.iter().enumerate() {
+            self.registers.set_register(register as u8, *value);
+        }
+        self.registers.set_index(snapshot.index);
+        self.registers.set_program_counter(snapshot.program_counter);
+        self.stack.restore(&snapshot.stack);
+        self.delay_timer = snapshot.delay_timer;
+        self.sound_timer = snapshot.sound_timer;
+        self.keys = snapshot.keys;
+        self.rpl_flags = snapshot.rpl_flags;
+        self.audio_pattern = snapshot.audio_pattern;
+        self.pitch = snapshot.pitch;
+        self.random = Random::from_state(snapshot.random_state);
+        self.is_waiting_for_frame = snapshot.is_waiting_for_frame;
+        self.is_halted = snapshot.is_halted;
+        self.key_wait = snapshot.key_wait;
+        Ok(())
+    }
+
+    /// Skips the next instruction, including both words of the XO-CHIP long
+    /// index load.
+    fn skip_instruction(&mut self) {
+        // An instruction past the end of memory is left for the next fetch to
+        // report
+        let size = match self
+            .memory
+            .get_instruction(self.registers.get_program_counter())
+        {
+            Ok(skipped) if skipped.is_supported_on(self.platform) => skipped.size(),
+            _ => 2,
+        };
+        self.registers.increase_program_counter(size);
+    }
+
+    fn execute(&mut self, instruction: Instruction, address: u16) -> Result<(), Chip8Error> {
+        match instruction {
+            Instruction::ClearScreen => self.display.clear(),
+            Instruction::Return => self.pop_stack(address)?,
+            Instruction::ScrollDown(lines) => {
+                self.display.framebuffer_mut().scroll_down(lines as usize)
             }
-            (0x8, _, _, 0x5) => self.substract_registers(
-                second_nibble as u8,
-                third_nibble as u8,
-                second_nibble as u8,
-            ),
-            (0x8, _, _, 0x6) => self.right_shift(second_nibble as u8, third_nibble as u8),
-            (0x8, _, _, 0x7) => self.substract_registers(
-                third_nibble as u8,
-                second_nibble as u8,
-                second_nibble as u8,
-            ),
-            (0x8, _, _, 0xE) => self.left_shift(second_nibble as u8, third_nibble as u8),
-            (0x9, _, _, 0x0) => self.skip_if_reg_not_eq(second_nibble as u8, third_nibble as u8),
-            (0xA, _, _, _) => {
-                self.registers
-                    .set_index((second_nibble << 8) | (third_nibble << 4) | fourth_nibble);
+            Instruction::ScrollUp(lines) => {
+                self.display.framebuffer_mut().scroll_up(lines as usize)
             }
-            (0xB, _, _, _) => {
-                self.jump_address_offset((second_nibble << 8) | (third_nibble << 4) | fourth_nibble)
+            Instruction::ScrollRight => self.display.framebuffer_mut().scroll_right(4),
+            Instruction::ScrollLeft => self.display.framebuffer_mut().scroll_left(4),
+            Instruction::Exit => self.is_halted = true,
+            Instruction::LowRes => self.display.framebuffer_mut().set_hires(false),
+            Instruction::HighRes => self.display.framebuffer_mut().set_hires(true),
+            Instruction::Jump(address) => self.registers.set_program_counter(address),
+            Instruction::Call(target) => self.push_stack(target, address)?,
+            Instruction::SkipIfEqual { x, value } => self.skip_if_eq_register(x, value),
+            Instruction::SkipIfNotEqual { x, value } => self.skip_if_not_eq_register(x, value),
+            Instruction::SkipIfRegistersEqual { x, y } => self.skip_if_reg_equals(x, y),
+            Instruction::SaveRange { x, y } => self.save_register_range(x, y)?,
+            Instruction::LoadRange { x, y } => self.load_register_range(x, y)?,
+            Instruction::Load { x, value } => self.registers.set_register(x, value),
+            Instruction::Add { x, value } => self.add_to_register(x, value),
+            Instruction::Move { x, y } => self.set_register_value(x, y),
+            Instruction::Or { x, y } => self.or_registers(x, y, x),
+            Instruction::And { x, y } => self.and_registers(x, y, x),
+            Instruction::Xor { x, y } => self.xor_registers(x, y, x),
+            Instruction::AddRegisters { x, y } => self.add_registers(x, y, x),
+            Instruction::Subtract { x, y } => self.substract_registers(x, y, x),
+            Instruction::ShiftRight { x, y } => self.right_shift(x, y),
+            Instruction::SubtractReversed { x, y } => self.substract_registers(y, x, x),
+            Instruction::ShiftLeft { x, y } => self.left_shift(x, y),
+            Instruction::SkipIfRegistersNotEqual { x, y } => self.skip_if_reg_not_eq(x, y),
+            Instruction::LoadIndex(address) => self.registers.set_index(address),
+            Instruction::JumpOffset { address, x } => self.jump_address_offset(address, x),
+            Instruction::Random { x, mask } => self.generate_random_number(x, mask),
+            Instruction::Draw { x, y, rows } => self.draw_to_screen(x, y, rows)?,
+            Instruction::SkipIfKey(x) => self.skip_if_key_pressed(x),
+            Instruction::SkipIfNotKey(x) => self.skip_if_not_key_pressed(x),
+            Instruction::LoadLongIndex => self.load_long_index()?,
+            Instruction::SelectPlanes(planes) => {
+                self.display.framebuffer_mut().select_planes(planes)
             }
-            (0xC, _, _, _) => self.generate_random_number(
-                second_nibble as u8,
-                ((third_nibble << 4) | fourth_nibble) as u8,
-            ),
-            (0xD, _, _, _) => {
-                self.draw_to_screen(second_nibble as u8, third_nibble as u8, fourth_nibble as u8)
+            Instruction::LoadAudioPattern => self.load_audio_pattern()?,
+            Instruction::GetDelayTimer(x) => self.set_reg_to_delay_timer(x),
+            Instruction::WaitKey(x) => self.get_key(x),
+            Instruction::SetDelayTimer(x) => self.set_delay_timer(x),
+            Instruction::SetSoundTimer(x) => self.set_sound_timer(x),
+            Instruction::AddToIndex(x) => self.add_to_index(x),
+            Instruction::LoadFont(x) => self.set_index_to_font(x),
+            Instruction::LoadBigFont(x) => self.set_index_to_big_font(x),
+            Instruction::StoreBcd(x) => self.binary_coded_decimal_conversion(x)?,
+            Instruction::SetPitch(x) => self.set_pitch(x),
+            Instruction::StoreRegisters(x) => self.store_in_memory(x)?,
+            Instruction::LoadRegisters(x) => self.load_from_memory(x)?,
+            Instruction::StoreFlags(x) => self.store_rpl_flags(x),
+            Instruction::LoadFlags(x) => self.load_rpl_flags(x),
+            Instruction::Unknown(opcode) => {
+                return Err(Chip8Error::InvalidOpcode { address, opcode })
             }
-            (0xE, _, 0x9, 0xE) => self.skip_if_key_pressed(second_nibble as u8),
-            (0xE, _, 0xA, 0x1) => self.skip_if_not_key_pressed(second_nibble as u8),
-            (0xF, _, 0x0, 0x7) => self.set_reg_to_delay_timer(second_nibble as u8),
-            (0xF, _, 0x1, 0x5) => self.set_delay_timer(second_nibble as u8),
-            (0xF, _, 0x1, 0x8) => self.set_sound_timer(second_nibble as u8),
-            (0xF, _, 0x1, 0xE) => self.add_to_index(second_nibble as u8),
-            (0xF, _, 0x0, 0xA) => self.get_key(second_nibble as u8),
-            (0xF, _, 0x2, 0x9) => self.set_index_to_font(second_nibble as u8),
-            (0xF, _, 0x3, 0x3) => self.binary_coded_decimal_conversion(second_nibble as u8),
-            (0xF, _, 0x5, 0x5) => self.store_in_memory(second_nibble as u8),
-            (0xF, _, 0x6, 0x5) => self.load_from_memory(second_nibble as u8),
-            _ => eprintln!("Incorrect opcode: {opcode:#x}"),
-        }
-    }
-
-    fn pop_stack(&mut self) {
-        let new_pc = self.stack.pop();
-        if let Some(new_pc) = new_pc {
-            self.registers.set_program_counter(new_pc);
-        } else {
-            eprintln!("Nothing in the stack to pop");
         }
+        Ok(())
     }
 
-    fn push_stack(&mut self, address: u16) {
-        self.stack.push(self.registers.get_program_counter());
-        self.registers.set_program_counter(address);
+    fn pop_stack(&mut self, address: u16) -> Result<(), Chip8Error> {
+        let new_pc = self.stack.pop(&self.memory, address)?;
+        self.registers.set_program_counter(new_pc);
+        Ok(())
+    }
+
+    fn push_stack(&mut self, target: u16, address: u16) -> Result<(), Chip8Error> {
+        self.stack.push(
+            &mut self.memory,
+            self.registers.get_program_counter(),
+            address,
+        )?;
+        self.registers.set_program_counter(target);
+        Ok(())
     }
 
     fn skip_if_eq_register(&mut self, register: u8, value: u8) {
-        let register_value = self.registers.get_register(register as usize);
-        if let Some(register_value) = register_value {
-            let skip = register_value == value;
-            if skip {
-                self.fetch_instruction();
-            }
-        } else {
-            eprintln!("Invalid register {register}");
+        let skip = self.registers.get_register(register) == value;
+        if skip {
+            self.skip_instruction();
         }
     }
 
     fn skip_if_not_eq_register(&mut self, register: u8, value: u8) {
-        let register_value = self.registers.get_register(register as usize);
-        if let Some(register_value) = register_value {
-            let skip = register_value != value;
-            if skip {
-                self.fetch_instruction();
-            }
-        } else {
-            eprintln!("Invalid register {register}");
+        let skip = self.registers.get_register(register) != value;
+        if skip {
+            self.skip_instruction();
         }
     }
 
     fn skip_if_reg_equals(&mut self, reg_1: u8, reg_2: u8) {
-        let first_value = self.registers.get_register(reg_1 as usize);
-        let second_value = self.registers.get_register(reg_2 as usize);
-        match (first_value, second_value) {
-            (Some(first_value), Some(second_value)) => {
-                let skip = first_value == second_value;
-                if skip {
-                    self.fetch_instruction();
-                }
-            }
-            (None, None) => {
-                eprintln!("Invalid registers: {reg_1}, {reg_2}")
-            }
-            (None, Some(_)) => eprintln!("Invalid register: {reg_1}"),
-            (Some(_), None) => eprintln!("Invalid register: {reg_2}"),
+        let skip = self.registers.get_register(reg_1) == self.registers.get_register(reg_2);
+        if skip {
+            self.skip_instruction();
         }
     }
 
     fn add_to_register(&mut self, register: u8, value: u8) {
-        let register_value = self.registers.get_register(register as usize);
-        if let Some(register_value) = register_value {
-            self.registers
-                .set_register(register as usize, register_value.wrapping_add(value));
-        }
+        let register_value = self.registers.get_register(register);
+        self.registers
+            .set_register(register, register_value.wrapping_add(value));
     }
 
     fn set_register_value(&mut self, dest_register: u8, source_register: u8) {
-        let value = self
-            .registers
-            .get_register(source_register as usize)
-            .unwrap();
-        self.registers.set_register(dest_register as usize, value);
+        let value = self.registers.get_register(source_register);
+        self.registers.set_register(dest_register, value);
     }
 
     fn or_registers(&mut self, reg_1: u8, reg_2: u8, dest_register: u8) {
-        let value_1 = self.registers.get_register(reg_1 as usize).unwrap();
-        let value_2 = self.registers.get_register(reg_2 as usize).unwrap();
+        let value_1 = self.registers.get_register(reg_1);
+        let value_2 = self.registers.get_register(reg_2);
         let new_value = value_1 | value_2;
-        self.registers
-            .set_register(dest_register as usize, new_value);
+        self.registers.set_register(dest_register, new_value);
+        if self.quirks.logic_resets_flag {
+            self.registers.reset_flag();
+        }
     }
 
     fn and_registers(&mut self, reg_1: u8, reg_2: u8, dest_register: u8) {
-        let value_1 = self.registers.get_register(reg_1 as usize).unwrap();
-        let value_2 = self.registers.get_register(reg_2 as usize).unwrap();
+        let value_1 = self.registers.get_register(reg_1);
+        let value_2 = self.registers.get_register(reg_2);
         let new_value = value_1 & value_2;
-        self.registers
-            .set_register(dest_register as usize, new_value);
+        self.registers.set_register(dest_register, new_value);
+        if self.quirks.logic_resets_flag {
+            self.registers.reset_flag();
+        }
     }
 
     fn xor_registers(&mut self, reg_1: u8, reg_2: u8, dest_register: u8) {
-        let value_1 = self.registers.get_register(reg_1 as usize).unwrap();
-        let value_2 = self.registers.get_register(reg_2 as usize).unwrap();
+        let value_1 = self.registers.get_register(reg_1);
+        let value_2 = self.registers.get_register(reg_2);
         let new_value = value_1 ^ value_2;
-        self.registers
-            .set_register(dest_register as usize, new_value);
+        self.registers.set_register(dest_register, new_value);
+        if self.quirks.logic_resets_flag {
+            self.registers.reset_flag();
+        }
     }
 
     fn add_registers(&mut self, reg_1: u8, reg_2: u8, dest_register: u8) {
-        let value_1 = self.registers.get_register(reg_1 as usize).unwrap();
-        let value_2 = self.registers.get_register(reg_2 as usize).unwrap();
+        let value_1 = self.registers.get_register(reg_1);
+        let value_2 = self.registers.get_register(reg_2);
         let (new_value, did_overflow) = value_1.overflowing_add(value_2);
-        self.registers
-            .set_register(dest_register as usize, new_value);
+        self.registers.set_register(dest_register, new_value);
         if did_overflow {
             self.registers.set_flag();
         } else {
@@ -390,11 +528,10 @@ impl CPU {
     }
 
     fn substract_registers(&mut self, reg_1: u8, reg_2: u8, dest_register: u8) {
-        let value_1 = self.registers.get_register(reg_1 as usize).unwrap();
-        let value_2 = self.registers.get_register(reg_2 as usize).unwrap();
+        let value_1 = self.registers.get_register(reg_1);
+        let value_2 = self.registers.get_register(reg_2);
         let (new_value, did_borrow) = value_1.overflowing_sub(value_2);
-        self.registers
-            .set_register(dest_register as usize, new_value);
+        self.registers.set_register(dest_register, new_value);
         if did_borrow {
             self.registers.reset_flag();
         } else {
@@ -403,14 +540,15 @@ impl CPU {
     }
 
     fn right_shift(&mut self, dest_register: u8, source_register: u8) {
-        let source_value = self
-            .registers
-            .get_register(source_register as usize)
-            .unwrap();
+        let source_register = if self.quirks.shift_uses_vy {
+            source_register
+        } else {
+            dest_register
+        };
+        let source_value = self.registers.get_register(source_register);
         let did_overflow = source_value & 1 == 1;
         let new_value = source_value >> 1;
-        self.registers
-            .set_register(dest_register as usize, new_value);
+        self.registers.set_register(dest_register, new_value);
         if did_overflow {
             self.registers.set_flag();
         } else {
@@ -418,15 +556,16 @@ impl CPU {
         }
     }
 
-    fn left_shift(&mut self, source_register: u8, dest_register: u8) {
-        let source_value = self
-            .registers
-            .get_register(source_register as usize)
-            .unwrap();
+    fn left_shift(&mut self, dest_register: u8, source_register: u8) {
+        let source_register = if self.quirks.shift_uses_vy {
+            source_register
+        } else {
+            dest_register
+        };
+        let source_value = self.registers.get_register(source_register);
         let did_overflow = source_value >> 7 == 1;
         let new_value = source_value << 1;
-        self.registers
-            .set_register(dest_register as usize, new_value);
+        self.registers.set_register(dest_register, new_value);
         if did_overflow {
             self.registers.set_flag();
         } else {
@@ -435,141 +574,523 @@ impl CPU {
     }
 
     fn skip_if_reg_not_eq(&mut self, reg_1: u8, reg_2: u8) {
-        let value_1 = self.registers.get_register(reg_1 as usize);
-        let value_2 = self.registers.get_register(reg_2 as usize);
-        match (value_1, value_2) {
-            (Some(first_value), Some(second_value)) => {
-                let skip = first_value != second_value;
-                if skip {
-                    self.fetch_instruction();
-                }
-            }
-            (None, None) => {
-                eprintln!("Invalid registers: {reg_1}, {reg_2}")
-            }
-            (None, Some(_)) => eprintln!("Invalid register: {reg_1}"),
-            (Some(_), None) => eprintln!("Invalid register: {reg_2}"),
+        let skip = self.registers.get_register(reg_1) != self.registers.get_register(reg_2);
+        if skip {
+            self.skip_instruction();
         }
     }
 
-    fn jump_address_offset(&mut self, base_address: u16) {
-        let offset = self.registers.get_register(0).unwrap() as u16;
-        self.registers.set_program_counter(base_address + offset);
+    fn jump_address_offset(&mut self, base_address: u16, register: u8) {
+        let register = if self.quirks.jump_uses_vx {
+            register
+        } else {
+            0
+        };
+        let offset = self.registers.get_register(register) as u16;
+        self.registers
+            .set_program_counter(base_address.wrapping_add(offset));
     }
 
     fn generate_random_number(&mut self, register: u8, mask: u8) {
-        let random_number = rand::thread_rng().gen::<u8>() & 0xFF;
-        self.registers
-            .set_register(register as usize, random_number & mask);
+        let random_number = self.random.next_byte();
+        self.registers.set_register(register, random_number & mask);
     }
 
-    fn draw_to_screen(&mut self, reg_x: u8, reg_y: u8, number_bytes: u8) {
-        if let (Some(x_position), Some(y_position)) = (
-            self.registers.get_register(reg_x as usize),
-            self.registers.get_register(reg_y as usize),
-        ) {
-            let x_position = x_position & 63;
-            let y_position = y_position & 31;
-            let bytes = self
-                .memory
-                .get_slice(self.registers.get_index(), number_bytes as u16);
-            let did_flip_on_pixel = self.display.draw(x_position, y_position, bytes);
-            if did_flip_on_pixel {
-                self.registers.set_flag();
-            } else {
-                self.registers.reset_flag();
-            }
-            self.display.refresh();
+    fn draw_to_screen(&mut self, reg_x: u8, reg_y: u8, number_bytes: u8) -> Result<(), Chip8Error> {
+        let x_position = self.registers.get_register(reg_x);
+        let y_position = self.registers.get_register(reg_y);
+        let is_big_sprite = number_bytes == 0 && self.platform.has_superchip_instructions();
+        let (number_bytes, bytes_per_row) = if is_big_sprite {
+            (32, 2)
         } else {
-            eprintln!("Invalid registers {reg_x} {reg_y}");
+            (number_bytes as u16, 1)
+        };
+        let plane_count = self.display.framebuffer().selected_plane_count() as u16;
+        let bytes = self.memory.get_slice(
+            self.registers.get_index(),
+            number_bytes * plane_count.max(1),
+        )?;
+        let framebuffer = self.display.framebuffer_mut();
+        let collided_rows = framebuffer.draw_sprite(
+            x_position,
+            y_position,
+            bytes,
+            bytes_per_row,
+            self.quirks.clip_sprites,
+        );
+        if self.platform == Platform::SuperChip && framebuffer.is_hires() {
+            self.registers.set_register(0xF, collided_rows);
+        } else if collided_rows > 0 {
+            self.registers.set_flag();
+        } else {
+            self.registers.reset_flag();
         }
+        self.display.refresh();
+        self.is_waiting_for_frame = self.quirks.display_wait;
+        Ok(())
     }
 
     fn skip_if_key_pressed(&mut self, register: u8) {
-        let register_value = self.registers.get_register(register as usize).unwrap();
-        let skip = self.keys[register_value as usize];
+        let register_value = self.registers.get_register(register);
+        let skip = self.keys[(register_value & 0xF) as usize];
         if skip {
-            self.fetch_instruction();
+            self.skip_instruction();
         }
     }
 
     fn skip_if_not_key_pressed(&mut self, register: u8) {
-        let register_value = self.registers.get_register(register as usize).unwrap();
-        let skip = !self.keys[register_value as usize];
+        let register_value = self.registers.get_register(register);
+        let skip = !self.keys[(register_value & 0xF) as usize];
         if skip {
-            self.fetch_instruction();
+            self.skip_instruction();
         }
     }
 
     fn set_reg_to_delay_timer(&mut self, register: u8) {
-        self.registers
-            .set_register(register as usize, self.delay_timer);
+        self.registers.set_register(register, self.delay_timer);
     }
 
     fn set_delay_timer(&mut self, register: u8) {
-        self.delay_timer = self.registers.get_register(register as usize).unwrap();
+        self.delay_timer = self.registers.get_register(register);
     }
 
     fn set_sound_timer(&mut self, register: u8) {
-        self.sound_timer = self.registers.get_register(register as usize).unwrap();
+        self.sound_timer = self.registers.get_register(register);
     }
 
     fn add_to_index(&mut self, register: u8) {
-        let register_value = self.registers.get_register(register as usize).unwrap() as u16;
+        let register_value = self.registers.get_register(register) as u16;
         let index = self.registers.get_index();
-        self.registers.set_index(index + register_value);
+        self.registers.set_index(index.wrapping_add(register_value));
     }
 
+    /// Stops execution until the next key press, see [`KeyWait`]. Timers keep
+    /// running in the meantime.
     fn get_key(&mut self, register: u8) {
-        let mut found_key = false;
-        for (key, is_key_pressed) in self.keys.iter().enumerate() {
-            if *is_key_pressed {
-                self.registers.set_register(register as usize, key as u8);
-                found_key = true;
-                break;
-            }
-        }
-        if !found_key {
-            self.registers
-                .set_program_counter(self.registers.get_program_counter().wrapping_sub(2));
+        self.key_wait = Some(KeyWait::new(register, self.keys));
+    }
+
+    fn update_key_wait(&mut self) {
+        let Some(key_wait) = self.key_wait.as_mut() else {
+            return;
+        };
+        if let Some(key) = key_wait.update(&self.keys, self.quirks.key_wait_release) {
+            self.registers.set_register(key_wait.register, key);
+            self.key_wait = None;
         }
     }
 
     fn set_index_to_font(&mut self, register: u8) {
-        let register_value = self.registers.get_register(register as usize).unwrap();
+        let register_value = self.registers.get_register(register);
         let character = register_value & 0xF;
         let character_address = self.memory.get_font_address(character);
         self.registers.set_index(character_address);
     }
 
-    fn binary_coded_decimal_conversion(&mut self, register: u8) {
-        let register_value = self.registers.get_register(register as usize).unwrap();
+    fn set_index_to_big_font(&mut self, register: u8) {
+        let register_value = self.registers.get_register(register);
+        let character = register_value & 0xF;
+        let character_address = self.memory.get_big_font_address(character);
+        self.registers.set_index(character_address);
+    }
+
+    fn binary_coded_decimal_conversion(&mut self, register: u8) -> Result<(), Chip8Error> {
+        let register_value = self.registers.get_register(register);
         let first_digit = register_value / 100;
         let second_digit = (register_value / 10) % 10;
         let third_digit = register_value % 10;
         let base_address = self.registers.get_index();
-        self.memory.set_value(base_address, first_digit);
+        self.memory.set_value(base_address, first_digit)?;
         self.memory
-            .set_value(base_address.wrapping_add(1), second_digit);
+            .set_value(base_address.wrapping_add(1), second_digit)?;
         self.memory
-            .set_value(base_address.wrapping_add(2), third_digit);
+            .set_value(base_address.wrapping_add(2), third_digit)
     }
 
-    fn store_in_memory(&mut self, register: u8) {
+    fn store_in_memory(&mut self, register: u8) -> Result<(), Chip8Error> {
         let base_index = self.registers.get_index();
         for reg in 0..=register {
             self.memory.set_value(
-                base_index + reg as u16,
-                self.registers.get_register(reg as usize).unwrap(),
-            );
+                base_index.wrapping_add(reg as u16),
+                self.registers.get_register(reg),
+            )?;
         }
+        if self.quirks.load_store_increments_index {
+            self.registers
+                .set_index(base_index.wrapping_add(register as u16 + 1));
+        }
+        Ok(())
     }
 
-    fn load_from_memory(&mut self, register: u8) {
+    fn load_from_memory(&mut self, register: u8) -> Result<(), Chip8Error> {
         let base_index = self.registers.get_index();
         for reg in 0..=register {
+            let value = self.memory.get_value(base_index.wrapping_add(reg as u16))?;
+            self.registers.set_register(reg, value);
+        }
+        if self.quirks.load_store_increments_index {
             self.registers
-                .set_register(reg as usize, self.memory.get_value(base_index + reg as u16));
+                .set_index(base_index.wrapping_add(register as u16 + 1));
+        }
+        Ok(())
+    }
+
+    fn store_rpl_flags(&mut self, register: u8) {
+        let last_flag = (register as usize).min(self.platform.rpl_flag_count() - 1);
+        for flag in 0..=last_flag {
+            self.rpl_flags[flag] = self.registers.get_register(flag as u8);
+        }
+    }
+
+    fn load_rpl_flags(&mut self, register: u8) {
+        let last_flag = (register as usize).min(self.platform.rpl_flag_count() - 1);
+        for flag in 0..=last_flag {
+            self.registers
+                .set_register(flag as u8, self.rpl_flags[flag]);
+        }
+    }
+
+    fn save_register_range(
+        &mut self,
+        first_register: u8,
+        last_register: u8,
+    ) -> Result<(), Chip8Error> {
+        let base_index = self.registers.get_index();
+        for (offset, reg) in register_range(first_register, last_register).enumerate() {
+            self.memory.set_value(
+                base_index.wrapping_add(offset as u16),
+                self.registers.get_register(reg),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn load_register_range(
+        &mut self,
+        first_register: u8,
+        last_register: u8,
+    ) -> Result<(), Chip8Error> {
+        let base_index = self.registers.get_index();
+        for (offset, reg) in register_range(first_register, last_register).enumerate() {
+            let value = self
+                .memory
+                .get_value(base_index.wrapping_add(offset as u16))?;
+            self.registers.set_register(reg, value);
+        }
+        Ok(())
+    }
+
+    fn load_long_index(&mut self) -> Result<(), Chip8Error> {
+        let address = self.memory.get_word(self.registers.get_program_counter())?;
+        self.registers.increase_program_counter(2);
+        self.registers.set_index(address);
+        Ok(())
+    }
+
+    fn load_audio_pattern(&mut self) -> Result<(), Chip8Error> {
+        let mut pattern = [0; 16];
+        pattern.copy_from_slice(self.memory.get_slice(self.registers.get_index(), 16)?);
+        self.audio_pattern = Some(pattern);
+        Ok(())
+    }
+
+    fn set_pitch(&mut self, register: u8) {
+        self.pitch = self.registers.get_register(register);
+    }
+}
+
+/// Registers from `first` to `last` inclusive, counting down when `first` is
+/// the larger one.
+fn register_range(first: u8, last: u8) -> Box<dyn Iterator<Item = u8>> {
+    if first <= last {
+        Box::new(first..=last)
+    } else {
+        Box::new((last..=first).rev())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Loads `rom` at 0x200 and runs `steps` instructions.
+    fn run(cpu: &mut CPU<HeadlessDisplay>, rom: &[u8], steps: usize) {
+        cpu.load_rom(rom).unwrap();
+        for _ in 0..steps {
+            cpu.step().unwrap();
+        }
+    }
+
+    #[test]
+    fn key_skips_mask_out_of_range_keys() {
+        let mut cpu = CPU::new();
+        cpu.load_rom(&[0x60, 0x20, 0xE0, 0x9E]).unwrap();
+        cpu.set_key(0x0, true);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.get_program_counter(), 0x206);
+    }
+
+    #[test]
+    fn runs_an_instruction_overwritten_by_fx55() {
+        let mut cpu = CPU::new();
+        let rom = [
+            0x62, 0x01, // V2 = 1, replaced by V2 = 7
+            0x60, 0x62, 0x61, 0x07, // V0 = 0x62, V1 = 0x07
+            0xA2, 0x00, 0xF1, 0x55, // store V0 and V1 at 0x200
+            0x12, 0x00, // jump back
+        ];
+        run(&mut cpu, &rom, 7);
+        assert_eq!(cpu.registers.get_register(2), 7);
+    }
+
+    #[test]
+    fn runs_an_instruction_whose_second_byte_bcd_overwrote() {
+        let mut cpu = CPU::new();
+        let rom = [
+            0x12, 0x0C, // run 0x20C once before it changes
+            0x60, 105, 0xA2, 0x0D, 0xF0, 0x33, // digits 1, 0, 5 at 0x20D
+            0x12, 0x0C, 0x00, 0x00, // jump to it again, padding
+            0x63, 0xAA, // V3 = 0xAA, then V3 = 1
+            0x12, 0x02, // jump back
+        ];
+        run(&mut cpu, &rom, 8);
+        assert_eq!(cpu.registers.get_register(3), 1);
+    }
+
+    /// Runs `rom` with a quirk off, then on, and returns the two CPUs.
+    fn run_with_quirk(
+        set_quirk: impl Fn(&mut Quirks, bool),
+        rom: &[u8],
+        steps: usize,
+    ) -> [CPU<HeadlessDisplay>; 2] {
+        [false, true].map(|is_on| {
+            let mut quirks = Quirks::default();
+            set_quirk(&mut quirks, is_on);
+            let mut cpu = CPU::new();
+            cpu.set_quirks(quirks);
+            run(&mut cpu, rom, steps);
+            cpu
+        })
+    }
+
+    #[test]
+    fn shifts_vy_or_vx() {
+        let shift_uses_vy = |quirks: &mut Quirks, is_on| quirks.shift_uses_vy = is_on;
+        let [off, on] = run_with_quirk(shift_uses_vy, &[0x60, 0x01, 0x61, 0x04, 0x80, 0x16], 3);
+        assert_eq!(off.registers.get_register(0), 0);
+        assert_eq!(on.registers.get_register(0), 2);
+        let [off, on] = run_with_quirk(shift_uses_vy, &[0x60, 0x01, 0x61, 0x04, 0x80, 0x1E], 3);
+        assert_eq!(off.registers.get_register(0), 2);
+        assert_eq!(on.registers.get_register(0), 8);
+    }
+
+    #[test]
+    fn jumps_with_v0_or_vx() {
+        let [off, on] = run_with_quirk(
+            |quirks, is_on| quirks.jump_uses_vx = is_on,
+            &[0x60, 0x10, 0x62, 0x20, 0xB2, 0x30],
+            3,
+        );
+        assert_eq!(off.registers.get_program_counter(), 0x240);
+        assert_eq!(on.registers.get_program_counter(), 0x250);
+    }
+
+    #[test]
+    fn leaves_i_past_the_registers_loaded_and_stored() {
+        for opcode in [0x55, 0x65] {
+            let [off, on] = run_with_quirk(
+                |quirks, is_on| quirks.load_store_increments_index = is_on,
+                &[0xA3, 0x00, 0xF2, opcode],
+                2,
+            );
+            assert_eq!(off.registers.get_index(), 0x300);
+            assert_eq!(on.registers.get_index(), 0x303);
+        }
+    }
+
+    #[test]
+    fn resets_vf_on_logic_operations() {
+        for opcode in [0x11, 0x12, 0x13] {
+            let [off, on] = run_with_quirk(
+                |quirks, is_on| quirks.logic_resets_flag = is_on,
+                &[0x6F, 0x05, 0x80, opcode],
+                2,
+            );
+            assert_eq!(off.registers.get_register(0xF), 5);
+            assert_eq!(on.registers.get_register(0xF), 0);
         }
     }
+
+    #[test]
+    fn clips_or_wraps_sprites_at_the_edge() {
+        // An 8 pixel row drawn at x = 62 leaves 6 pixels past the edge
+        let rom = [
+            0x60, 62, 0x61, 0x00, 0xA2, 0x0A, 0xD0, 0x11, 0x00, 0x00, 0xFF,
+        ];
+        let [off, on] = run_with_quirk(|quirks, is_on| quirks.clip_sprites = is_on, &rom, 4);
+        for cpu in [&off, &on] {
+            assert!(cpu.framebuffer().get_pixel(63, 0));
+        }
+        assert!(off.framebuffer().get_pixel(5, 0));
+        assert!(!on.framebuffer().get_pixel(0, 0));
+    }
+
+    #[test]
+    fn waits_for_the_display_after_drawing() {
+        let rom = [0xD0, 0x01, 0x70, 0x01];
+        let [off, on] = run_with_quirk(|quirks, is_on| quirks.display_wait = is_on, &rom, 2);
+        assert_eq!(off.registers.get_register(0), 1);
+        assert_eq!(on.registers.get_register(0), 0);
+        assert!(on.is_waiting());
+        let mut on = on;
+        on.tick_timers();
+        on.step().unwrap();
+        assert_eq!(on.registers.get_register(0), 1);
+    }
+
+    #[test]
+    fn draws_on_a_headless_display() {
+        let mut cpu = CPU::with_display(HeadlessDisplay::new());
+        // The 0 of the font at 0x50 is F0 90 90 90 F0
+        run(&mut cpu, &[0x00, 0xE0, 0xA0, 0x50, 0xD0, 0x05], 3);
+        let framebuffer = cpu.framebuffer();
+        assert!(framebuffer.get_pixel(0, 0) && framebuffer.get_pixel(3, 0));
+        assert!(framebuffer.get_pixel(0, 1) && !framebuffer.get_pixel(1, 1));
+        assert!(!framebuffer.get_pixel(4, 0));
+        assert_eq!(cpu.registers.get_register(0xF), 0);
+        cpu.registers.set_program_counter(0x204);
+        cpu.step().unwrap();
+        assert!(!cpu.framebuffer().get_pixel(0, 0));
+        assert_eq!(cpu.registers.get_register(0xF), 1);
+    }
+
+    fn cpu_on(platform: Platform) -> CPU<HeadlessDisplay> {
+        let mut cpu = CPU::new();
+        cpu.set_platform(platform);
+        cpu
+    }
+
+    #[test]
+    fn draws_16x16_sprites_counting_colliding_rows() {
+        let mut cpu = cpu_on(Platform::SuperChip);
+        let mut rom = vec![0x00, 0xFF, 0xA2, 0x08, 0xD0, 0x10, 0xD0, 0x10];
+        rom.extend([0xFF; 32]);
+        run(&mut cpu, &rom, 3);
+        assert!(cpu.framebuffer().get_pixel(15, 15));
+        assert!(!cpu.framebuffer().get_pixel(16, 0) && !cpu.framebuffer().get_pixel(0, 16));
+        assert_eq!(cpu.registers.get_register(0xF), 0);
+        cpu.step().unwrap();
+        assert_eq!(cpu.registers.get_register(0xF), 16);
+    }
+
+    #[test]
+    fn draws_each_selected_plane_from_its_own_bytes() {
+        let mut cpu = cpu_on(Platform::XoChip);
+        run(
+            &mut cpu,
+            &[0xF3, 0x01, 0xA2, 0x06, 0xD0, 0x01, 0x80, 0x40],
+            3,
+        );
+        assert_eq!(cpu.framebuffer().get_pixel_planes(0, 0), 0b01);
+        assert_eq!(cpu.framebuffer().get_pixel_planes(1, 0), 0b10);
+    }
+
+    #[test]
+    fn scrolls_only_the_selected_planes() {
+        let mut cpu = cpu_on(Platform::XoChip);
+        let rom = [
+            0xF3, 0x01, 0xA2, 0x10, 0xD0, 0x01, // a pixel on both planes
+            0xF1, 0x01, // then only the first one
+            0x00, 0xC2, 0x00, 0xFB, 0x00, 0xFC, // down 2, right 4, left 4
+            0x12, 0x0E, 0x80, 0x80, // loop, sprite
+        ];
+        run(&mut cpu, &rom, 4);
+        assert_eq!(cpu.framebuffer().get_pixel_planes(0, 0), 0b11);
+        cpu.step().unwrap();
+        assert_eq!(cpu.framebuffer().get_pixel_planes(0, 0), 0b10);
+        assert_eq!(cpu.framebuffer().get_pixel_planes(0, 2), 0b01);
+        cpu.step().unwrap();
+        assert_eq!(cpu.framebuffer().get_pixel_planes(0, 2), 0);
+        assert_eq!(cpu.framebuffer().get_pixel_planes(4, 2), 0b01);
+        cpu.step().unwrap();
+        assert_eq!(cpu.framebuffer().get_pixel_planes(0, 2), 0b01);
+        assert_eq!(cpu.framebuffer().get_pixel_planes(0, 0), 0b10);
+    }
+
+    #[test]
+    fn saves_and_loads_register_ranges_in_both_directions() {
+        let mut cpu = cpu_on(Platform::XoChip);
+        let rom = [
+            0x61, 0x01, 0x62, 0x02, 0x63, 0x03, // V1 = 1, V2 = 2, V3 = 3
+            0xA3, 0x00, 0x51, 0x32, // V1 to V3 at 0x300
+            0xA3, 0x10, 0x53, 0x12, // V3 down to V1 at 0x310
+            0xA3, 0x00, 0x53, 0x13, // V3 down to V1 from 0x300
+        ];
+        run(&mut cpu, &rom, 9);
+        assert_eq!(cpu.memory().bytes()[0x300..0x303], [1, 2, 3]);
+        assert_eq!(cpu.memory().bytes()[0x310..0x313], [3, 2, 1]);
+        assert_eq!(cpu.registers.get_register(1), 3);
+        assert_eq!(cpu.registers.get_register(3), 1);
+    }
+
+    #[test]
+    fn skips_the_long_index_load_as_one_instruction() {
+        let mut cpu = cpu_on(Platform::XoChip);
+        let rom = [0x30, 0x00, 0xF0, 0x00, 0x03, 0x00, 0x61, 0x01];
+        run(&mut cpu, &rom, 2);
+        assert_eq!(cpu.registers.get_register(1), 1);
+        assert_eq!(cpu.registers.get_index(), 0);
+    }
+
+    #[test]
+    fn overflows_the_stack_at_its_depth() {
+        let mut cpu = CPU::new();
+        cpu.set_stack_depth(2);
+        run(&mut cpu, &[0x22, 0x00], 2);
+        assert_eq!(
+            cpu.step(),
+            Err(Chip8Error::StackOverflow { address: 0x200 })
+        );
+    }
+
+    #[test]
+    fn underflows_on_a_return_outside_a_subroutine() {
+        let mut cpu = CPU::new();
+        cpu.load_rom(&[0x00, 0xEE]).unwrap();
+        assert_eq!(
+            cpu.step(),
+            Err(Chip8Error::StackUnderflow { address: 0x200 })
+        );
+    }
+
+    #[test]
+    fn rejects_opcodes_of_other_platforms() {
+        for opcode in [0x00FF, 0xF000, 0x5012] {
+            let mut cpu = CPU::new();
+            cpu.load_rom(&u16::to_be_bytes(opcode)).unwrap();
+            assert_eq!(
+                cpu.step(),
+                Err(Chip8Error::InvalidOpcode {
+                    address: 0x200,
+                    opcode
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn reports_accesses_past_the_end_of_memory() {
+        let out_of_range = Err(Chip8Error::MemoryOutOfRange {
+            address: 0x1000,
+            size: 0x1000,
+        });
+        let mut cpu = CPU::new();
+        cpu.registers.set_program_counter(0xFFF);
+        assert_eq!(cpu.step(), out_of_range);
+        let mut cpu = CPU::new();
+        cpu.load_rom(&[0xAF, 0xFF, 0xF1, 0x55]).unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.step(), out_of_range);
+    }
 }
@@ -0,0 +1,60 @@
+use super::Registers;
+use crate::instruction::Instruction;
+
+/// Machine cycles the VIP interpreter spends fetching and decoding every
+/// instruction before running it.
+pub const FETCH_CYCLES: u32 = 68;
+/// Extra cycles a skip costs when it is taken.
+pub const SKIP_CYCLES: u32 = 4;
+
+/// Approximate cost of an instruction in COSMAC VIP machine cycles (8 clocks
+/// of the 1.76 MHz CDP1802), following the routines of the original
+/// interpreter. Instructions the VIP doesn't have cost only the fetch.
+pub fn get_vip_cycles(instruction: &Instruction, registers: &Registers) -> u32 {
+    let register = |x: &u8| registers.get_register(*x);
+    let execution = match instruction {
+        // Clearing runs over all 256 bytes of the display page
+        Instruction::ClearScreen => 3078,
+        Instruction::Return => 10,
+        Instruction::Jump(_) => 12,
+        Instruction::Call(_) => 26,
+        Instruction::SkipIfEqual { .. } | Instruction::SkipIfNotEqual { .. } => 10,
+        Instruction::SkipIfRegistersEqual { .. } | Instruction::SkipIfRegistersNotEqual { .. } => {
+            14
+        }
+        Instruction::Load { .. } => 6,
+        Instruction::Add { .. } => 10,
+        Instruction::Move { .. }
+        | Instruction::Or { .. }
+        | Instruction::And { .. }
+        | Instruction::Xor { .. }
+        | Instruction::AddRegisters { .. }
+        | Instruction::Subtract { .. }
+        | Instruction::ShiftRight { .. }
+        | Instruction::SubtractReversed { .. }
+        | Instruction::ShiftLeft { .. } => 44,
+        Instruction::LoadIndex(_) => 12,
+        Instruction::JumpOffset { .. } => 22,
+        Instruction::Random { .. } => 36,
+        // Sprite rows that don't start on a byte boundary are shifted across
+        // two bytes, which costs more
+        Instruction::Draw { x, rows, .. } => {
+            let row_cycles = if register(x) % 8 == 0 { 34 } else { 50 };
+            26 + *rows as u32 * row_cycles
+        }
+        Instruction::SkipIfKey(_) | Instruction::SkipIfNotKey(_) => 14,
+        Instruction::GetDelayTimer(_) => 10,
+        Instruction::WaitKey(_) => 19,
+        Instruction::SetDelayTimer(_) | Instruction::SetSoundTimer(_) => 10,
+        Instruction::AddToIndex(_) => 16,
+        Instruction::LoadFont(_) => 20,
+        // BCD is computed by repeated subtraction, one loop per unit
+        Instruction::StoreBcd(x) => {
+            let value = register(x) as u32;
+            84 + (value / 100 + value / 10 % 10 + value % 10) * 16
+        }
+        Instruction::StoreRegisters(x) | Instruction::LoadRegisters(x) => 14 + 14 * (*x as u32 + 1),
+        _ => 0,
+    };
+    FETCH_CYCLES + execution
+}
@@ -0,0 +1,77 @@
+/// An FX0A waiting for a key. Only keys pressed after the wait started
+/// count, so a key still held from an earlier prompt doesn't end it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyWait {
+    /// The register that receives the key.
+    pub register: u8,
+    /// Keys down at the last update.
+    pub held_keys: [bool; 16],
+    /// The key that was pressed, when the wait goes on until it is released.
+    pub pressed_key: Option<u8>,
+}
+
+impl KeyWait {
+    pub fn new(register: u8, keys: [bool; 16]) -> Self {
+        Self {
+            register,
+            held_keys: keys,
+            pressed_key: None,
+        }
+    }
+
+    /// Follows a change of the keys, returning the key once the wait is over:
+    /// on a new press, or on its release with `wait_for_release`.
+    pub fn update(&mut self, keys: &[bool; 16], wait_for_release: bool) -> Option<u8> {
+        let finished_key = match self.pressed_key {
+            Some(key) => (!keys[key as usize]).then_some(key),
+            None => {
+                let pressed_key = (0..16)
+                    .find(|key| keys[*key] && !self.held_keys[*key])
+                    .map(|key| key as u8);
+                if wait_for_release {
+                    self.pressed_key = pressed_key;
+                    None
+                } else {
+                    pressed_key
+                }
+            }
+        };
+        self.held_keys = *keys;
+        finished_key
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(pressed: &[usize]) -> [bool; 16] {
+        let mut keys = [false; 16];
+        for key in pressed {
+            keys[*key] = true;
+        }
+        keys
+    }
+
+    #[test]
+    fn ignores_a_key_held_when_the_wait_started() {
+        let mut key_wait = KeyWait::new(0, keys(&[5]));
+        assert_eq!(key_wait.update(&keys(&[5]), false), None);
+        assert_eq!(key_wait.update(&keys(&[]), false), None);
+        assert_eq!(key_wait.update(&keys(&[5]), false), Some(5));
+    }
+
+    #[test]
+    fn finishes_on_the_release_when_waiting_for_it() {
+        let mut key_wait = KeyWait::new(0, keys(&[]));
+        assert_eq!(key_wait.update(&keys(&[7]), true), None);
+        assert_eq!(key_wait.update(&keys(&[7, 2]), true), None);
+        assert_eq!(key_wait.update(&keys(&[2]), true), Some(7));
+    }
+
+    #[test]
+    fn finishes_on_the_press_otherwise() {
+        let mut key_wait = KeyWait::new(0, keys(&[1]));
+        assert_eq!(key_wait.update(&keys(&[1, 0xC]), false), Some(0xC));
+    }
+}
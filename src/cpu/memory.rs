@@ -1,8 +1,11 @@
-use core::panic;
-use std::usize;
+use crate::error::Chip8Error;
+use crate::instruction::Instruction;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
 
 const MEMORY_SIZE: usize = 4096;
-const INITIAL_POSITION: usize = 0x200;
+/// Where ROMs are loaded and execution starts.
+pub const INITIAL_POSITION: usize = 0x200;
 const FONT: [u8; 5 * 16] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, 0x20, 0x60, 0x20, 0x20, 0x70, 0xF0, 0x10, 0xF0, 0x80, 0xF0, 0xF0,
     0x10, 0xF0, 0x10, 0xF0, 0x90, 0x90, 0xF0, 0x10, 0x10, 0xF0, 0x80, 0xF0, 0x10, 0xF0, 0xF0, 0x80,
@@ -10,53 +13,275 @@ const FONT: [u8; 5 * 16] = [
     0x10, 0xF0, 0xF0, 0x90, 0xF0, 0x90, 0x90, 0xE0, 0x90, 0xE0, 0x90, 0xE0, 0xF0, 0x80, 0x80, 0x80,
     0xF0, 0xE0, 0x90, 0x90, 0x90, 0xE0, 0xF0, 0x80, 0xF0, 0x80, 0xF0, 0xF0, 0x80, 0xF0, 0x80, 0x80,
 ];
+const BIG_FONT_ADDRESS: u16 = 0xA0;
+const BIG_FONT: [u8; 10 * 16] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, 0x18, 0x38, 0x58, 0x18, 0x18, 0x18,
+    0x18, 0x18, 0x18, 0x3C, 0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, 0x3C, 0x7E,
+    0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, 0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF,
+    0x06, 0x06, 0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, 0x3E, 0x7C, 0xC0, 0xC0,
+    0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, 0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60,
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, 0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F,
+    0x03, 0x03, 0x3E, 0x7C, 0x18, 0x3C, 0x66, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xFC, 0xFE,
+    0xC3, 0xC3, 0xFE, 0xFE, 0xC3, 0xC3, 0xFE, 0xFC, 0x3C, 0x7E, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3,
+    0x7E, 0x3C, 0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, 0xFF, 0xFF, 0xC0, 0xC0,
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0,
+];
+
+/// Which accesses to an address a watchpoint reports.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Watch {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl Watch {
+    fn covers(&self, kind: AccessKind) -> bool {
+        match self {
+            Watch::Read => kind == AccessKind::Read,
+            Watch::Write => kind == AccessKind::Write,
+            Watch::ReadWrite => true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+/// A watched address being read or written. Reads have the same old and new
+/// value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryAccess {
+    pub address: u16,
+    pub kind: AccessKind,
+    pub old_value: u8,
+    pub new_value: u8,
+}
 
 pub struct Memory {
-    memory: [u8; MEMORY_SIZE],
+    memory: Vec<u8>,
+    /// Instructions decoded so far, by address. Writes clear the entries
+    /// their byte belongs to.
+    decoded: Vec<Option<Instruction>>,
+    watchpoints: BTreeMap<u16, Watch>,
+    accesses: RefCell<Vec<MemoryAccess>>,
 }
 
 impl Default for Memory {
     fn default() -> Self {
-        let mut memory = [0; 4096];
-        memory[0x050..=0x09F].copy_from_slice(&FONT);
-        Self { memory }
+        Self::new(MEMORY_SIZE)
     }
 }
 
 impl Memory {
-    pub fn get_value(&self, address: u16) -> u8 {
-        if address < 4096 {
-            self.memory[address as usize]
-        } else {
-            panic!("Invalid address");
+    pub fn new(size: usize) -> Self {
+        let mut memory = vec![0; size];
+        memory[0x050..=0x09F].copy_from_slice(&FONT);
+        memory[0x0A0..=0x13F].copy_from_slice(&BIG_FONT);
+        Self {
+            decoded: vec![None; memory.len()],
+            memory,
+            watchpoints: BTreeMap::new(),
+            accesses: RefCell::new(Vec::new()),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.memory.len()
+    }
+
+    /// Grows or shrinks the addressable memory, keeping what is already
+    /// stored in it.
+    pub fn resize(&mut self, size: usize) {
+        self.memory.resize(size, 0);
+        self.decoded = vec![None; size];
+    }
+
+    /// The whole address space, without triggering watchpoints.
+    pub fn bytes(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// Replaces the whole address space, for restoring save states.
+    pub fn restore(&mut self, bytes: &[u8]) {
+        self.memory = bytes.to_vec();
+        self.decoded = vec![None; bytes.len()];
+    }
+
+    pub fn add_watchpoint(&mut self, address: u16, watch: Watch) {
+        self.watchpoints.insert(address, watch);
+    }
+
+    pub fn remove_watchpoint(&mut self, address: u16) -> bool {
+        self.watchpoints.remove(&address).is_some()
+    }
+
+    pub fn watchpoints(&self) -> impl Iterator<Item = (&u16, &Watch)> {
+        self.watchpoints.iter()
+    }
+
+    /// Hands over the watched accesses made since the last call.
+    pub fn take_accesses(&self) -> Vec<MemoryAccess> {
+        self.accesses.take()
+    }
+
+    fn record_access(&self, address: u16, kind: AccessKind, old_value: u8, new_value: u8) {
+        if let Some(watch) = self.watchpoints.get(&address) {
+            if watch.covers(kind) {
+                self.accesses.borrow_mut().push(MemoryAccess {
+                    address,
+                    kind,
+                    old_value,
+                    new_value,
+                });
+            }
         }
     }
 
-    pub fn set_value(&mut self, address: u16, value: u8) {
-        if address < 4096 {
-            self.memory[address as usize] = value;
-        } else {
-            panic!("Invalid address");
+    /// Reads a byte without triggering watchpoints, for instruction fetches
+    /// and debugger views.
+    pub fn peek(&self, address: u16) -> Result<u8, Chip8Error> {
+        self.memory
+            .get(address as usize)
+            .copied()
+            .ok_or_else(|| self.out_of_range(address as usize))
+    }
+
+    /// Reads the big-endian word at `address` without triggering watchpoints.
+    pub fn get_word(&self, address: u16) -> Result<u16, Chip8Error> {
+        let high = self.peek(address)?;
+        let low = self.peek(address.wrapping_add(1))?;
+        Ok((high as u16) << 8 | low as u16)
+    }
+
+    /// Decodes the instruction at `address`, reusing the previous decode as
+    /// long as neither of its bytes has been written since.
+    pub fn get_instruction(&mut self, address: u16) -> Result<Instruction, Chip8Error> {
+        if let Some(Some(instruction)) = self.decoded.get(address as usize) {
+            return Ok(*instruction);
         }
+        let instruction = Instruction::decode(self.get_word(address)?);
+        self.decoded[address as usize] = Some(instruction);
+        Ok(instruction)
     }
 
-    pub fn get_slice(&self, address: u16, number_bytes: u16) -> &[u8] {
-        &self.memory[address as usize..(address + number_bytes) as usize]
+    pub fn get_value(&self, address: u16) -> Result<u8, Chip8Error> {
+        let value = self.peek(address)?;
+        if !self.watchpoints.is_empty() {
+            self.record_access(address, AccessKind::Read, value, value);
+        }
+        Ok(value)
     }
 
-    pub fn load(&mut self, data: &[u8]) {
-        for (position, value) in data.iter().enumerate() {
-            if position < MEMORY_SIZE - INITIAL_POSITION {
-                self.memory[position + INITIAL_POSITION] = *value;
+    pub fn set_value(&mut self, address: u16, value: u8) -> Result<(), Chip8Error> {
+        let Some(byte) = self.memory.get_mut(address as usize) else {
+            return Err(self.out_of_range(address as usize));
+        };
+        let old_value = *byte;
+        *byte = value;
+        self.invalidate(address as usize);
+        if !self.watchpoints.is_empty() {
+            self.record_access(address, AccessKind::Write, old_value, value);
+        }
+        Ok(())
+    }
+
+    pub fn get_slice(&self, address: u16, number_bytes: u16) -> Result<&[u8], Chip8Error> {
+        let start = address as usize;
+        let end = start + number_bytes as usize;
+        let slice = self
+            .memory
+            .get(start..end)
+            .ok_or_else(|| self.out_of_range(end - 1))?;
+        if !self.watchpoints.is_empty() {
+            for (offset, value) in slice.iter().enumerate() {
+                self.record_access(
+                    address.wrapping_add(offset as u16),
+                    AccessKind::Read,
+                    *value,
+                    *value,
+                );
             }
         }
+        Ok(slice)
     }
 
-    pub fn get_font_address(&self, value: u8) -> u16 {
-        if value <= 0xF {
-            (0x50 + 5 * value) as u16
-        } else {
-            panic!("Invalid character");
+    /// Copies a ROM to `address`, usually [`INITIAL_POSITION`], leaving memory
+    /// untouched when it doesn't fit.
+    pub fn load(&mut self, address: u16, data: &[u8]) -> Result<(), Chip8Error> {
+        let start = address as usize;
+        if start >= self.memory.len() {
+            return Err(self.out_of_range(start));
+        }
+        let max_size = self.memory.len() - start;
+        if data.len() > max_size {
+            return Err(Chip8Error::RomTooLarge {
+                size: data.len(),
+                max_size,
+            });
+        }
+        self.memory[start..start + data.len()].copy_from_slice(data);
+        self.decoded.fill(None);
+        Ok(())
+    }
+
+    fn out_of_range(&self, address: usize) -> Chip8Error {
+        Chip8Error::MemoryOutOfRange {
+            address,
+            size: self.memory.len(),
+        }
+    }
+
+    /// Forgets the decoded instructions that overlap the byte at `address`.
+    fn invalidate(&mut self, address: usize) {
+        for start in address.saturating_sub(1)..=address {
+            self.decoded[start] = None;
         }
     }
+
+    /// Address of the digit for the low nibble of `value`.
+    pub fn get_font_address(&self, value: u8) -> u16 {
+        0x50 + 5 * (value & 0xF) as u16
+    }
+
+    /// Address of the 8x10 SUPER-CHIP digit used by FX30.
+    pub fn get_big_font_address(&self, value: u8) -> u16 {
+        BIG_FONT_ADDRESS + 10 * (value & 0xF) as u16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_roms_too_large_to_fit() {
+        let mut memory = Memory::default();
+        let rom = vec![0xAA; MEMORY_SIZE - INITIAL_POSITION + 1];
+        assert_eq!(
+            memory.load(INITIAL_POSITION as u16, &rom),
+            Err(Chip8Error::RomTooLarge {
+                size: rom.len(),
+                max_size: MEMORY_SIZE - INITIAL_POSITION,
+            })
+        );
+        assert_eq!(memory.peek(INITIAL_POSITION as u16), Ok(0));
+        assert!(memory.load(INITIAL_POSITION as u16, &rom[1..]).is_ok());
+        assert_eq!(memory.peek(0xFFF), Ok(0xAA));
+    }
+
+    #[test]
+    fn rejects_load_addresses_past_the_end() {
+        let mut memory = Memory::default();
+        assert_eq!(
+            memory.load(0x1000, &[0xAA]),
+            Err(Chip8Error::MemoryOutOfRange {
+                address: 0x1000,
+                size: MEMORY_SIZE,
+            })
+        );
+    }
 }
@@ -0,0 +1,60 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The xorshift64* generator behind CXNN. Its whole state is one word, so it
+/// is cheap to store in save states, and the same seed always produces the
+/// same numbers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Random {
+    state: u64,
+}
+
+impl Default for Random {
+    /// Seeds from the clock, so every run differs.
+    fn default() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_nanos() as u64);
+        Self::new(nanos)
+    }
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        Self::from_state(splitmix64(seed))
+    }
+
+    /// Continues from a state returned by [`Random::state`].
+    pub fn from_state(state: u64) -> Self {
+        // xorshift never leaves 0, so it is not a valid state
+        Self {
+            state: if state == 0 {
+                0x9E3779B97F4A7C15
+            } else {
+                state
+            },
+        }
+    }
+
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545F4914F6CDD1D)
+    }
+
+    pub fn next_byte(&mut self) -> u8 {
+        (self.next_u64() >> 56) as u8
+    }
+}
+
+/// Spreads the seed bits so that nearby seeds give unrelated sequences.
+fn splitmix64(seed: u64) -> u64 {
+    let mut value = seed.wrapping_add(0x9E3779B97F4A7C15);
+    value = (value ^ (value >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94D049BB133111EB);
+    value ^ (value >> 31)
+}
@@ -1,5 +1,6 @@
 const INITIAL_PC: u16 = 0x200;
 
+#[derive(Clone)]
 pub struct Registers {
     general_registers: [u8; 16],
     index: u16,
@@ -25,10 +26,6 @@ impl Registers {
         self.general_registers[0xF] = 0x0;
     }
 
-    pub fn is_flag_set(&mut self) -> bool {
-        return self.general_registers[0xF] == 1;
-    }
-
     pub fn get_program_counter(&self) -> u16 {
         self.program_counter
     }
@@ -38,20 +35,17 @@ impl Registers {
     }
 
     pub fn increase_program_counter(&mut self, amount: u16) {
-        self.program_counter += amount;
+        self.program_counter = self.program_counter.wrapping_add(amount);
     }
 
-    pub fn set_register(&mut self, register: usize, value: u8) {
-        if register < 16 {
-            self.general_registers[register] = value;
-        }
+    /// Sets VX. Only the low nibble of `register` is used, like the X and Y
+    /// fields of an opcode.
+    pub fn set_register(&mut self, register: u8, value: u8) {
+        self.general_registers[(register & 0xF) as usize] = value;
     }
-    pub fn get_register(&mut self, register: usize) -> Option<u8> {
-        if register < 16 {
-            Some(self.general_registers[register])
-        } else {
-            None
-        }
+
+    pub fn get_register(&self, register: u8) -> u8 {
+        self.general_registers[(register & 0xF) as usize]
     }
 
     pub fn get_index(&self) -> u16 {
@@ -0,0 +1,221 @@
+use crate::binary::{self, Reader};
+use crate::cpu::KeyWait;
+use crate::platform::Platform;
+use std::fs;
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"C8SS";
+/// Stored for the pressed key of a key wait when there is none yet.
+const NO_KEY: u8 = 0xFF;
+/// Bumped whenever the layout below changes; older files are rejected.
+pub const SNAPSHOT_VERSION: u16 = 3;
+
+/// Everything needed to put a CPU back into an earlier state: registers,
+/// memory, stack, timers, keys and the screen. Quirks are left out, they
+/// belong to the configuration rather than the machine.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    pub platform: Platform,
+    pub registers: [u8; 16],
+    pub index: u16,
+    pub program_counter: u16,
+    pub stack: Vec<u16>,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub keys: [bool; 16],
+    pub rpl_flags: [u8; 16],
+    pub audio_pattern: Option<[u8; 16]>,
+    pub pitch: u8,
+    /// State of the CXNN random number generator.
+    pub random_state: u64,
+    pub is_waiting_for_frame: bool,
+    pub is_halted: bool,
+    pub key_wait: Option<KeyWait>,
+    pub is_hires: bool,
+    pub selected_planes: u8,
+    pub pixels: Vec<u8>,
+    pub memory: Vec<u8>,
+}
+
+impl Snapshot {
+    /// Serializes the snapshot as a versioned little-endian binary.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.memory.len() + self.pixels.len() + 128);
+        bytes.extend(MAGIC);
+        bytes.extend(SNAPSHOT_VERSION.to_le_bytes());
+        bytes.push(self.platform.id());
+        bytes.extend(self.registers);
+        bytes.extend(self.index.to_le_bytes());
+        bytes.extend(self.program_counter.to_le_bytes());
+        bytes.extend((self.stack.len() as u16).to_le_bytes());
+        for address in &self.stack {
+            bytes.extend(address.to_le_bytes());
+        }
+        bytes.push(self.delay_timer);
+        bytes.push(self.sound_timer);
+        bytes.extend(binary::keys_to_bits(&self.keys).to_le_bytes());
+        bytes.extend(self.rpl_flags);
+        match self.audio_pattern {
+            Some(pattern) => {
+                bytes.push(1);
+                bytes.extend(pattern);
+            }
+            None => bytes.push(0),
+        }
+        bytes.push(self.pitch);
+        bytes.extend(self.random_state.to_le_bytes());
+        bytes.push(
+            self.is_waiting_for_frame as u8
+                | (self.is_halted as u8) << 1
+                | (self.key_wait.is_some() as u8) << 2,
+        );
+        if let Some(key_wait) = &self.key_wait {
+            bytes.push(key_wait.register);
+            bytes.extend(binary::keys_to_bits(&key_wait.held_keys).to_le_bytes());
+            bytes.push(key_wait.pressed_key.unwrap_or(NO_KEY));
+        }
+        bytes.push(self.is_hires as u8);
+        bytes.push(self.selected_planes);
+        bytes.extend((self.pixels.len() as u32).to_le_bytes());
+        bytes.extend(&self.pixels);
+        bytes.extend((self.memory.len() as u32).to_le_bytes());
+        bytes.extend(&self.memory);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader::new(bytes, "Save state");
+        if reader.read_bytes(MAGIC.len())? != MAGIC {
+            return Err(String::from("Not a save state"));
+        }
+        let version = reader.read_u16()?;
+        if version != SNAPSHOT_VERSION {
+            return Err(format!(
+                "Save state version {version} is not supported, expected {SNAPSHOT_VERSION}"
+            ));
+        }
+        let platform_id = reader.read_u8()?;
+        let platform = Platform::from_id(platform_id)
+            .ok_or_else(|| format!("Unknown platform {platform_id} in save state"))?;
+        let registers = reader.read_array()?;
+        let index = reader.read_u16()?;
+        let program_counter = reader.read_u16()?;
+        let stack_size = reader.read_u16()?;
+        let stack = (0..stack_size)
+            .map(|_| reader.read_u16())
+            .collect::<Result<_, _>>()?;
+        let delay_timer = reader.read_u8()?;
+        let sound_timer = reader.read_u8()?;
+        let keys = reader.read_keys()?;
+        let rpl_flags = reader.read_array()?;
+        let audio_pattern = match reader.read_u8()? {
+            0 => None,
+            _ => Some(reader.read_array()?),
+        };
+        let pitch = reader.read_u8()?;
+        let random_state = reader.read_u64()?;
+        let flags = reader.read_u8()?;
+        let key_wait = if flags & 4 != 0 {
+            let register = reader.read_u8()? & 0xF;
+            let held_keys = reader.read_keys()?;
+            let pressed_key = reader.read_u8()?;
+            Some(KeyWait {
+                register,
+                held_keys,
+                pressed_key: (pressed_key != NO_KEY).then_some(pressed_key & 0xF),
+            })
+        } else {
+            None
+        };
+        let is_hires = reader.read_u8()? != 0;
+        let selected_planes = reader.read_u8()?;
+        let pixel_count = reader.read_u32()? as usize;
+        let pixels = reader.read_bytes(pixel_count)?.to_vec();
+        let memory_size = reader.read_u32()? as usize;
+        let memory = reader.read_bytes(memory_size)?.to_vec();
+        Ok(Self {
+            platform,
+            registers,
+            index,
+            program_counter,
+            stack,
+            delay_timer,
+            sound_timer,
+            keys,
+            rpl_flags,
+            audio_pattern,
+            pitch,
+            random_state,
+            is_waiting_for_frame: flags & 1 != 0,
+            is_halted: flags & 2 != 0,
+            key_wait,
+            is_hires,
+            selected_planes,
+            pixels,
+            memory,
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.to_bytes())
+            .map_err(|error| format!("Can't write {}: {error}", path.display()))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let bytes =
+            fs::read(path).map_err(|error| format!("Can't read {}: {error}", path.display()))?;
+        Self::from_bytes(&bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::CPU;
+
+    fn snapshot() -> Snapshot {
+        let mut snapshot = CPU::new().snapshot();
+        snapshot.platform = Platform::XoChip;
+        snapshot.registers[0xF] = 1;
+        snapshot.index = 0x345;
+        snapshot.stack = vec![0x202, 0x20A];
+        snapshot.delay_timer = 30;
+        snapshot.keys[5] = true;
+        snapshot.audio_pattern = Some([0xAA; 16]);
+        snapshot.is_halted = true;
+        snapshot.key_wait = Some(KeyWait {
+            register: 3,
+            held_keys: [true; 16],
+            pressed_key: Some(0xC),
+        });
+        snapshot.memory[0x200] = 0x12;
+        snapshot
+    }
+
+    #[test]
+    fn decodes_what_it_encodes() {
+        let snapshot = snapshot();
+        assert_eq!(Snapshot::from_bytes(&snapshot.to_bytes()), Ok(snapshot));
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut bytes = snapshot().to_bytes();
+        bytes[MAGIC.len()..MAGIC.len() + 2].copy_from_slice(&2u16.to_le_bytes());
+        assert_eq!(
+            Snapshot::from_bytes(&bytes),
+            Err(format!(
+                "Save state version 2 is not supported, expected {SNAPSHOT_VERSION}"
+            ))
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let bytes = snapshot().to_bytes();
+        assert_eq!(
+            Snapshot::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(String::from("Save state is truncated"))
+        );
+    }
+}
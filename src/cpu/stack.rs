@@ -0,0 +1,114 @@
+use super::Memory;
+use crate::error::Chip8Error;
+
+/// Subroutine levels available unless configured otherwise.
+pub const DEFAULT_STACK_DEPTH: usize = 16;
+/// Where the COSMAC VIP interpreter kept its stack.
+pub const VIP_STACK_ADDRESS: u16 = 0xEA0;
+
+/// Return addresses of the subroutines in progress, at most `depth` of them.
+pub struct Stack {
+    entries: Vec<u16>,
+    depth: usize,
+    /// When set, entries are also stored big-endian in emulated memory from
+    /// this address on, and returns read them back from there so that ROMs
+    /// rewriting their stack behave as on the VIP.
+    address: Option<u16>,
+}
+
+impl Default for Stack {
+    fn default() -> Self {
+        Self {
+            entries: Vec::with_capacity(DEFAULT_STACK_DEPTH),
+            depth: DEFAULT_STACK_DEPTH,
+            address: None,
+        }
+    }
+}
+
+impl Stack {
+    pub fn entries(&self) -> &[u16] {
+        &self.entries
+    }
+
+    pub fn get_depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn set_depth(&mut self, depth: usize) {
+        self.depth = depth;
+    }
+
+    pub fn get_address(&self) -> Option<u16> {
+        self.address
+    }
+
+    pub fn set_address(&mut self, address: Option<u16>) {
+        self.address = address;
+    }
+
+    /// Replaces the entries, for restoring save states. Memory is expected to
+    /// be restored separately.
+    pub fn restore(&mut self, entries: &[u16]) {
+        self.entries = entries.to_vec();
+    }
+
+    /// Saves `value` for the call at `address`.
+    pub fn push(
+        &mut self,
+        memory: &mut Memory,
+        value: u16,
+        address: u16,
+    ) -> Result<(), Chip8Error> {
+        if self.entries.len() >= self.depth {
+            return Err(Chip8Error::StackOverflow { address });
+        }
+        if let Some(entry_address) = self.entry_address(self.entries.len()) {
+            let [high, low] = value.to_be_bytes();
+            memory.set_value(entry_address, high)?;
+            memory.set_value(entry_address.wrapping_add(1), low)?;
+        }
+        self.entries.push(value);
+        Ok(())
+    }
+
+    /// Takes the address the return at `address` goes back to.
+    pub fn pop(&mut self, memory: &Memory, address: u16) -> Result<u16, Chip8Error> {
+        let Some(value) = self.entries.pop() else {
+            return Err(Chip8Error::StackUnderflow { address });
+        };
+        match self.entry_address(self.entries.len()) {
+            Some(entry_address) => memory.get_word(entry_address),
+            None => Ok(value),
+        }
+    }
+
+    fn entry_address(&self, level: usize) -> Option<u16> {
+        self.address
+            .map(|address| address.wrapping_add(2 * level as u16))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_vip_stack_in_memory() {
+        let mut memory = Memory::default();
+        let mut stack = Stack::default();
+        stack.set_address(Some(VIP_STACK_ADDRESS));
+        stack.push(&mut memory, 0x202, 0x300).unwrap();
+        stack.push(&mut memory, 0x30A, 0x400).unwrap();
+        let start = VIP_STACK_ADDRESS as usize;
+        assert_eq!(memory.bytes()[start..start + 4], [0x02, 0x02, 0x03, 0x0A]);
+        // Returns go wherever the program rewrote its stack to
+        memory.set_value(VIP_STACK_ADDRESS + 3, 0x0C).unwrap();
+        assert_eq!(stack.pop(&memory, 0x500), Ok(0x30C));
+        assert_eq!(stack.pop(&memory, 0x500), Ok(0x202));
+        assert_eq!(
+            stack.pop(&memory, 0x500),
+            Err(Chip8Error::StackUnderflow { address: 0x500 })
+        );
+    }
+}
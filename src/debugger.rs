@@ -0,0 +1,704 @@
+use crate::cpu::{AccessKind, Registers, Watch, CPU};
+use crate::display::Display;
+use crate::error::{Chip8Error, ErrorPolicy};
+use crate::instruction::Instruction;
+use std::collections::BTreeSet;
+use std::fmt::{self, Write};
+use std::io::{self, BufRead};
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+const HELP: &str = "\
+Commands (addresses are hexadecimal):
+  c, continue        resume execution
+  p, pause           pause execution
+  s, step            execute one instruction
+  n, next            step over a subroutine call (2NNN)
+  f, finish          run until the current subroutine returns (00EE)
+  b, break ADDR      set a breakpoint
+  d, delete ADDR     remove a breakpoint
+  w, watch ADDR [r|w|rw]
+                     stop when memory is read and/or written (default rw)
+  uw, unwatch ADDR   remove a watchpoint
+  bc, break-if VX|I OP VALUE
+                     stop when a register starts to meet a condition,
+                     OP is one of == != < <= > >=
+  dc, delete-if N    remove the condition listed as number N
+  l, list            list breakpoints, watchpoints and conditions
+  i, info            show registers, stack, timers and keys
+  m, mem ADDR [LEN]  dump memory
+  q, quit            stop the emulator
+  h, help            show this help";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    const OPERATORS: [(&'static str, Comparison); 6] = [
+        ("==", Comparison::Equal),
+        ("!=", Comparison::NotEqual),
+        ("<=", Comparison::LessOrEqual),
+        (">=", Comparison::GreaterOrEqual),
+        ("<", Comparison::Less),
+        (">", Comparison::Greater),
+    ];
+
+    fn operator(&self) -> &'static str {
+        Self::OPERATORS
+            .iter()
+            .find(|(_, comparison)| comparison == self)
+            .map(|(operator, _)| *operator)
+            .unwrap_or("?")
+    }
+}
+
+/// The register a [`Condition`] compares: one of V0-VF or the index I.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operand {
+    Register(u8),
+    Index,
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Register(register) => write!(f, "V{register:X}"),
+            Operand::Index => write!(f, "I"),
+        }
+    }
+}
+
+/// A register condition such as `V3 == 0x10` or `I >= 0x300`, used as a
+/// conditional breakpoint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Condition {
+    pub operand: Operand,
+    pub comparison: Comparison,
+    pub value: u16,
+}
+
+impl Condition {
+    pub fn holds(&self, registers: &Registers) -> bool {
+        let operand_value = self.get_operand_value(registers);
+        match self.comparison {
+            Comparison::Equal => operand_value == self.value,
+            Comparison::NotEqual => operand_value != self.value,
+            Comparison::Less => operand_value < self.value,
+            Comparison::LessOrEqual => operand_value <= self.value,
+            Comparison::Greater => operand_value > self.value,
+            Comparison::GreaterOrEqual => operand_value >= self.value,
+        }
+    }
+
+    fn get_operand_value(&self, registers: &Registers) -> u16 {
+        match self.operand {
+            Operand::Register(register) => registers.get_register(register) as u16,
+            Operand::Index => registers.get_index(),
+        }
+    }
+}
+
+/// Parses `VX OP VALUE` or `I OP VALUE` with a hexadecimal value, spaces
+/// optional.
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text: String = text.split_whitespace().collect();
+        let (operator, comparison) = Comparison::OPERATORS
+            .iter()
+            .find(|(operator, _)| text.contains(operator))
+            .ok_or_else(|| format!("Missing comparison in {text}"))?;
+        let (operand, value) = text.split_once(operator).unwrap_or_default();
+        let operand = if operand.eq_ignore_ascii_case("I") {
+            Operand::Index
+        } else {
+            operand
+                .strip_prefix(['V', 'v'])
+                .and_then(|register| u8::from_str_radix(register, 16).ok())
+                .filter(|register| *register < 16)
+                .map(Operand::Register)
+                .ok_or_else(|| format!("Invalid register {operand}"))?
+        };
+        let value = parse_number(value)
+            .filter(|value| operand == Operand::Index || *value <= 0xFF)
+            .ok_or_else(|| format!("Invalid value {value}"))?;
+        Ok(Self {
+            operand,
+            comparison: *comparison,
+            value,
+        })
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {:#04X}",
+            self.operand,
+            self.comparison.operator(),
+            self.value
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mode {
+    Running,
+    Paused,
+    /// Pause after the next instruction.
+    Step,
+    /// Pause once the stack is back to this depth, so calls run to completion.
+    StepOver {
+        depth: usize,
+    },
+    /// Pause once the stack is shallower than this depth.
+    RunToReturn {
+        depth: usize,
+    },
+}
+
+/// Step debugger driven by text commands. The runner hands every instruction
+/// to [`Debugger::step`] instead of calling [`CPU::step`] directly.
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+    conditions: Vec<Condition>,
+    mode: Mode,
+    is_resuming: bool,
+    has_quit: bool,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self {
+            breakpoints: BTreeSet::new(),
+            conditions: Vec::new(),
+            mode: Mode::Paused,
+            is_resuming: false,
+            has_quit: false,
+        }
+    }
+}
+
+impl Debugger {
+    /// Creates a debugger that starts paused before the first instruction.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.mode == Mode::Paused
+    }
+
+    pub fn has_quit(&self) -> bool {
+        self.has_quit
+    }
+
+    pub fn pause(&mut self) {
+        self.mode = Mode::Paused;
+    }
+
+    /// Lets execution run until a breakpoint, e.g. for a debugger that should
+    /// only stop on errors.
+    pub fn resume_running(&mut self) {
+        self.mode = Mode::Running;
+    }
+
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = &u16> {
+        self.breakpoints.iter()
+    }
+
+    pub fn add_condition(&mut self, condition: Condition) {
+        self.conditions.push(condition);
+    }
+
+    pub fn remove_condition(&mut self, number: usize) -> Option<Condition> {
+        (number < self.conditions.len()).then(|| self.conditions.remove(number))
+    }
+
+    pub fn conditions(&self) -> &[Condition] {
+        &self.conditions
+    }
+
+    /// Executes one instruction unless the debugger is paused or a breakpoint
+    /// is hit. Returns a report when execution stops, or the error the
+    /// instruction ran into.
+    pub fn step<D: Display>(&mut self, cpu: &mut CPU<D>) -> Result<Option<String>, Chip8Error> {
+        if self.mode == Mode::Paused {
+            return Ok(None);
+        }
+        let program_counter = cpu.registers().get_program_counter();
+        if !self.is_resuming && self.breakpoints.contains(&program_counter) {
+            self.mode = Mode::Paused;
+            return Ok(Some(format!(
+                "Breakpoint at {program_counter:#06X}\n{}",
+                format_state(cpu)
+            )));
+        }
+        self.is_resuming = false;
+        let opcode = read_opcode(cpu, program_counter);
+        let old_registers = cpu.registers().clone();
+        cpu.step()?;
+        let new_registers = cpu.registers();
+        let mut reasons: Vec<String> = cpu
+            .take_memory_accesses()
+            .iter()
+            .map(|access| {
+                let kind = match access.kind {
+                    AccessKind::Read => "read",
+                    AccessKind::Write => "write",
+                };
+                format!(
+                    "Watchpoint: {kind} of {:#06X}, {:02X} -> {:02X}",
+                    access.address, access.old_value, access.new_value
+                )
+            })
+            .collect();
+        for condition in &self.conditions {
+            if !condition.holds(&old_registers) && condition.holds(new_registers) {
+                reasons.push(format!(
+                    "Condition {condition} met: {} {:02X} -> {:02X}",
+                    condition.operand,
+                    condition.get_operand_value(&old_registers),
+                    condition.get_operand_value(new_registers)
+                ));
+            }
+        }
+        if !reasons.is_empty() {
+            self.mode = Mode::Paused;
+            return Ok(Some(format!(
+                "{}\nTriggered by [{opcode:04X}] at {program_counter:#06X}\n{}",
+                reasons.join("\n"),
+                format_state(cpu)
+            )));
+        }
+        let depth = cpu.stack().len();
+        let reason = match self.mode {
+            Mode::Step => "Stepped",
+            Mode::StepOver { depth: target } if depth <= target => "Stepped",
+            Mode::RunToReturn { depth: target } if depth < target => "Returned",
+            _ => return Ok(None),
+        };
+        self.mode = Mode::Paused;
+        Ok(Some(format!("{reason}\n{}", format_state(cpu))))
+    }
+
+    /// Applies a debugger command and returns the text to show for it.
+    pub fn execute<D: Display>(&mut self, command: &str, cpu: &mut CPU<D>) -> String {
+        let mut words = command.split_whitespace();
+        let Some(name) = words.next() else {
+            return String::new();
+        };
+        let arguments: Vec<&str> = words.collect();
+        match name {
+            "c" | "continue" => self.resume(Mode::Running, "Continuing"),
+            "p" | "pause" => {
+                self.mode = Mode::Paused;
+                format_state(cpu)
+            }
+            "s" | "step" => self.resume(Mode::Step, ""),
+            "n" | "next" => self.resume(
+                Mode::StepOver {
+                    depth: cpu.stack().len(),
+                },
+                "",
+            ),
+            "f" | "finish" => {
+                let depth = cpu.stack().len();
+                if depth == 0 {
+                    return String::from("Not inside a subroutine");
+                }
+                self.resume(Mode::RunToReturn { depth }, "Running to return")
+            }
+            "b" | "break" => match parse_address(arguments.first()) {
+                Ok(address) => {
+                    self.add_breakpoint(address);
+                    format!("Breakpoint set at {address:#06X}")
+                }
+                Err(error) => error,
+            },
+            "d" | "delete" => match parse_address(arguments.first()) {
+                Ok(address) if self.remove_breakpoint(address) => {
+                    format!("Breakpoint removed from {address:#06X}")
+                }
+                Ok(address) => format!("No breakpoint at {address:#06X}"),
+                Err(error) => error,
+            },
+            "w" | "watch" => {
+                let watch = match arguments.get(1).copied() {
+                    None | Some("rw") => Watch::ReadWrite,
+                    Some("r") => Watch::Read,
+                    Some("w") => Watch::Write,
+                    Some(kind) => return format!("Unknown watch kind {kind}, expected r, w or rw"),
+                };
+                match parse_address(arguments.first()) {
+                    Ok(address) if (address as usize) < cpu.memory().size() => {
+                        cpu.add_watchpoint(address, watch);
+                        format!("Watching {address:#06X}")
+                    }
+                    Ok(address) => format!("Address {address:#06X} is out of memory"),
+                    Err(error) => error,
+                }
+            }
+            "uw" | "unwatch" => match parse_address(arguments.first()) {
+                Ok(address) if cpu.remove_watchpoint(address) => {
+                    format!("Stopped watching {address:#06X}")
+                }
+                Ok(address) => format!("No watchpoint at {address:#06X}"),
+                Err(error) => error,
+            },
+            "bc" | "break-if" => match arguments.join(" ").parse::<Condition>() {
+                Ok(condition) => {
+                    self.add_condition(condition);
+                    format!("Breaking when {condition}")
+                }
+                Err(error) => error,
+            },
+            "dc" | "delete-if" => {
+                let removed = arguments
+                    .first()
+                    .and_then(|number| number.parse::<usize>().ok())
+                    .and_then(|number| self.remove_condition(number));
+                match removed {
+                    Some(condition) => format!("Removed condition {condition}"),
+                    None => String::from("No such condition"),
+                }
+            }
+            "l" | "list" => self.format_breakpoints(cpu),
+            "i" | "info" => format_state(cpu),
+            "m" | "mem" => match parse_address(arguments.first()) {
+                Ok(address) => {
+                    let length = arguments
+                        .get(1)
+                        .and_then(|length| parse_number(length))
+                        .unwrap_or(16);
+                    format_memory(cpu, address, length)
+                }
+                Err(error) => error,
+            },
+            "q" | "quit" => {
+                self.has_quit = true;
+                String::from("Quitting")
+            }
+            "h" | "help" => String::from(HELP),
+            _ => format!("Unknown command {name}, type help for a list"),
+        }
+    }
+
+    fn format_breakpoints<D: Display>(&self, cpu: &CPU<D>) -> String {
+        let mut lines: Vec<String> = self
+            .breakpoints
+            .iter()
+            .map(|address| format!("Breakpoint {address:#06X}"))
+            .collect();
+        lines.extend(cpu.memory().watchpoints().map(|(address, watch)| {
+            let kind = match watch {
+                Watch::Read => "r",
+                Watch::Write => "w",
+                Watch::ReadWrite => "rw",
+            };
+            format!("Watchpoint {address:#06X} {kind}")
+        }));
+        lines.extend(
+            self.conditions
+                .iter()
+                .enumerate()
+                .map(|(number, condition)| format!("Condition {number}: {condition}")),
+        );
+        if lines.is_empty() {
+            String::from("No breakpoints")
+        } else {
+            lines.join("\n")
+        }
+    }
+
+    fn resume(&mut self, mode: Mode, message: &str) -> String {
+        self.mode = mode;
+        self.is_resuming = true;
+        String::from(message)
+    }
+}
+
+/// What happened during [`step`] that the user should be told about.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Report {
+    /// The debugger stopped, with the reason and the CPU state.
+    Stopped(String),
+    /// The instruction failed and the CPU halted.
+    Halted(Chip8Error),
+    /// The instruction failed and execution carries on.
+    Ignored(Chip8Error),
+}
+
+/// Executes one instruction, through `debugger` when there is one, handling
+/// errors according to `policy`. Returns `false` when execution should stop,
+/// because the debugger is paused or the CPU halted, along with anything to
+/// report.
+pub fn step<D: Display>(
+    debugger: Option<&mut Debugger>,
+    cpu: &mut CPU<D>,
+    policy: ErrorPolicy,
+) -> (bool, Option<Report>) {
+    let result = match debugger {
+        Some(debugger) if debugger.is_paused() => return (false, None),
+        Some(debugger) => match debugger.step(cpu) {
+            Ok(report) => return (true, report.map(Report::Stopped)),
+            Err(error) if policy == ErrorPolicy::Debug => {
+                debugger.pause();
+                let report = format!("{error}\n{}", format_state(cpu));
+                return (false, Some(Report::Stopped(report)));
+            }
+            Err(error) => Err(error),
+        },
+        None => cpu.step(),
+    };
+    match (result, policy) {
+        (Ok(()), _) => (true, None),
+        (Err(error), ErrorPolicy::Halt) => {
+            cpu.halt();
+            (false, Some(Report::Halted(error)))
+        }
+        // Without a debugger to pause in, carry on as with Ignore
+        (Err(error), _) => (true, Some(Report::Ignored(error))),
+    }
+}
+
+/// Registers, stack, timers and keys of the CPU, one group per line.
+pub fn format_state<D: Display>(cpu: &CPU<D>) -> String {
+    let registers = cpu.registers();
+    let program_counter = registers.get_program_counter();
+    let opcode = read_opcode(cpu, program_counter);
+    let mut state = String::new();
+    let _ = writeln!(
+        state,
+        "PC {program_counter:#06X} [{opcode:04X}] {:<16}  I {:#06X}  DT {:02X}  ST {:02X}",
+        Instruction::decode(opcode).to_string(),
+        registers.get_index(),
+        cpu.get_delay_timer(),
+        cpu.get_sound_timer(),
+    );
+    for row in 0..2 {
+        let line: Vec<String> = (row * 8..row * 8 + 8)
+            .map(|register| format!("V{register:X} {:02X}", registers.get_register(register)))
+            .collect();
+        let _ = writeln!(state, "{}", line.join("  "));
+    }
+    let stack: Vec<String> = cpu
+        .stack()
+        .iter()
+        .map(|address| format!("{address:#06X}"))
+        .collect();
+    let _ = write!(
+        state,
+        "Stack {}/{} [{}]",
+        stack.len(),
+        cpu.get_stack_depth(),
+        stack.join(", ")
+    );
+    if let Some(address) = cpu.get_stack_address() {
+        let _ = write!(state, " at {address:#06X}");
+    }
+    state.push('\n');
+    let keys: String = cpu
+        .keys()
+        .iter()
+        .enumerate()
+        .map(|(key, is_pressed)| {
+            if *is_pressed {
+                char::from_digit(key as u32, 16)
+                    .unwrap_or('?')
+                    .to_ascii_uppercase()
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    let _ = write!(state, "Keys {keys}");
+    if cpu.is_waiting_for_key() {
+        state.push_str("  (waiting for a key)");
+    }
+    state
+}
+
+fn format_memory<D: Display>(cpu: &CPU<D>, address: u16, length: u16) -> String {
+    let memory = cpu.memory();
+    let end = (address as usize + length as usize).min(memory.size());
+    let mut dump = String::new();
+    for row_start in (address as usize..end).step_by(16) {
+        let row_end = (row_start + 16).min(end);
+        let bytes: Vec<String> = (row_start..row_end)
+            .map(|address| match memory.peek(address as u16) {
+                Ok(value) => format!("{value:02X}"),
+                Err(_) => String::from("--"),
+            })
+            .collect();
+        let _ = writeln!(dump, "{row_start:#06X}  {}", bytes.join(" "));
+    }
+    dump.trim_end().to_string()
+}
+
+fn read_opcode<D: Display>(cpu: &CPU<D>, address: u16) -> u16 {
+    cpu.memory().get_word(address).unwrap_or(0)
+}
+
+/// Reads a hexadecimal number, with or without a `0x` prefix.
+pub fn parse_number(text: &str) -> Option<u16> {
+    let text = text.trim_start_matches("0x").trim_start_matches("0X");
+    u16::from_str_radix(text, 16).ok()
+}
+
+fn parse_address(text: Option<&&str>) -> Result<u16, String> {
+    let text = text.ok_or_else(|| String::from("Missing address"))?;
+    parse_number(text).ok_or_else(|| format!("Invalid address {text}"))
+}
+
+/// Reads debugger commands from stdin on a separate thread, so a runner can
+/// poll for them without blocking its event loop.
+pub fn spawn_stdin_reader() -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Steps through `debugger` until it reports, giving up after `steps`.
+    fn run_until_report<D: Display>(
+        debugger: &mut Debugger,
+        cpu: &mut CPU<D>,
+        steps: usize,
+    ) -> String {
+        for _ in 0..steps {
+            if let Some(report) = debugger.step(cpu).unwrap() {
+                return report;
+            }
+        }
+        panic!("no report after {steps} steps");
+    }
+
+    #[test]
+    fn parses_conditions() {
+        let condition: Condition = "V3==5".parse().unwrap();
+        assert_eq!(condition.operand, Operand::Register(3));
+        assert_eq!(condition.comparison, Comparison::Equal);
+        assert_eq!(condition.value, 5);
+        let condition: Condition = "vA <= 0x10".parse().unwrap();
+        assert_eq!(condition.operand, Operand::Register(0xA));
+        assert_eq!(condition.comparison, Comparison::LessOrEqual);
+        assert_eq!(condition.value, 0x10);
+        let condition: Condition = "I>=300".parse().unwrap();
+        assert_eq!(condition.operand, Operand::Index);
+        assert_eq!(condition.comparison, Comparison::GreaterOrEqual);
+        assert_eq!(condition.value, 0x300);
+    }
+
+    #[test]
+    fn rejects_invalid_conditions() {
+        assert!("V3 5".parse::<Condition>().is_err());
+        assert!("VG == 5".parse::<Condition>().is_err());
+        assert!("X == 5".parse::<Condition>().is_err());
+        assert!("V0 == 100".parse::<Condition>().is_err());
+        assert!("V0 == zz".parse::<Condition>().is_err());
+    }
+
+    #[test]
+    fn stops_on_watched_writes() {
+        let mut cpu = CPU::new();
+        let rom = [
+            0x60, 0x7B, 0xA3, 0x00, // V0 = 0x7B, I = 0x300
+            0xF0, 0x33, // BCD of V0 at 0x300
+            0xF0, 0x55, // store V0 at 0x300
+        ];
+        cpu.load_rom(&rom).unwrap();
+        cpu.add_watchpoint(0x300, Watch::Write);
+        let mut debugger = Debugger::new();
+        debugger.resume_running();
+        let report = run_until_report(&mut debugger, &mut cpu, 4);
+        assert!(debugger.is_paused());
+        assert!(report.contains("write of 0x0300, 00 -> 01"));
+        assert!(report.contains("Triggered by [F033] at 0x0204"));
+        debugger.execute("c", &mut cpu);
+        let report = run_until_report(&mut debugger, &mut cpu, 4);
+        assert!(report.contains("write of 0x0300, 01 -> 7B"));
+        assert!(report.contains("Triggered by [F055] at 0x0206"));
+    }
+
+    #[test]
+    fn stops_when_a_condition_starts_to_hold() {
+        let mut cpu = CPU::new();
+        let rom = [
+            0xA2, 0xFF, 0x70, 0x01, // I = 0x2FF, V0 += 1
+            0xF0, 0x1E, // I += V0
+            0x12, 0x02, // jump back to V0 += 1
+        ];
+        cpu.load_rom(&rom).unwrap();
+        let mut debugger = Debugger::new();
+        debugger.add_condition("I >= 300".parse().unwrap());
+        debugger.resume_running();
+        let report = run_until_report(&mut debugger, &mut cpu, 10);
+        assert!(report.contains("Condition I >= 0x300 met: I 2FF -> 300"));
+        assert!(report.contains("Triggered by [F01E] at 0x0204"));
+        debugger.add_condition("V0 == 3".parse().unwrap());
+        debugger.execute("c", &mut cpu);
+        let report = run_until_report(&mut debugger, &mut cpu, 10);
+        assert!(report.contains("Condition V0 == 0x03 met: V0 02 -> 03"));
+    }
+
+    #[test]
+    fn step_reports_errors_by_policy() {
+        let error = Chip8Error::InvalidOpcode {
+            address: 0x200,
+            opcode: 0x5012,
+        };
+        let mut cpu = CPU::new();
+        cpu.load_rom(&[0x50, 0x12]).unwrap();
+        let result = step(None, &mut cpu, ErrorPolicy::Ignore);
+        assert_eq!(result, (true, Some(Report::Ignored(error))));
+        let mut cpu = CPU::new();
+        cpu.load_rom(&[0x50, 0x12]).unwrap();
+        let result = step(None, &mut cpu, ErrorPolicy::Halt);
+        assert_eq!(result, (false, Some(Report::Halted(error))));
+        assert!(cpu.is_halted());
+        let mut cpu = CPU::new();
+        cpu.load_rom(&[0x50, 0x12]).unwrap();
+        let mut debugger = Debugger::new();
+        debugger.resume_running();
+        let (has_run, report) = step(Some(&mut debugger), &mut cpu, ErrorPolicy::Debug);
+        assert!(!has_run && debugger.is_paused());
+        assert!(
+            matches!(report, Some(Report::Stopped(report)) if report.starts_with(&error.to_string()))
+        );
+        assert_eq!(
+            step(Some(&mut debugger), &mut cpu, ErrorPolicy::Debug),
+            (false, None)
+        );
+    }
+}
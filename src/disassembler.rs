@@ -0,0 +1,238 @@
+use crate::instruction::Instruction;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+const DATA_BYTES_PER_LINE: usize = 8;
+const COMMENT_COLUMN: usize = 28;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Label {
+    /// Target of a jump or call.
+    Code,
+    /// Target of `LD I`, most likely sprite data.
+    Data,
+}
+
+/// Turns a ROM into an annotated listing the assembler can read back.
+///
+/// Code is found by following every path from `load_address`: jumps, calls
+/// and both outcomes of skips. Bytes that are never reached are printed as
+/// `DB` data, and those `LD I` points at are laid out one row per line with
+/// the sprite drawn in the comment.
+pub fn disassemble(rom: &[u8], load_address: u16) -> String {
+    let mut disassembler = Disassembler {
+        rom,
+        load_address,
+        instructions: BTreeMap::new(),
+        is_code: vec![false; rom.len()],
+        labels: BTreeMap::new(),
+    };
+    disassembler.trace();
+    disassembler.drop_unreachable_labels();
+    disassembler.listing()
+}
+
+struct Disassembler<'a> {
+    rom: &'a [u8],
+    load_address: u16,
+    /// Decoded instructions by offset into the ROM.
+    instructions: BTreeMap<usize, Instruction>,
+    /// Whether each byte belongs to an instruction.
+    is_code: Vec<bool>,
+    labels: BTreeMap<u16, Label>,
+}
+
+impl Disassembler<'_> {
+    fn trace(&mut self) {
+        let mut pending = vec![self.load_address];
+        while let Some(address) = pending.pop() {
+            let Some(offset) = self.get_offset(address) else {
+                continue;
+            };
+            if self.is_code[offset] {
+                continue;
+            }
+            let Some(instruction) = self.decode_at(offset) else {
+                continue;
+            };
+            let size = instruction.size() as usize;
+            if self.is_code[offset..offset + size]
+                .iter()
+                .any(|is_code| *is_code)
+            {
+                continue;
+            }
+            self.is_code[offset..offset + size].fill(true);
+            self.instructions.insert(offset, instruction);
+            let next = address.wrapping_add(size as u16);
+            match instruction {
+                Instruction::Return | Instruction::Exit => (),
+                Instruction::Jump(target)
+                | Instruction::JumpOffset {
+                    address: target, ..
+                } => {
+                    self.labels.insert(target, Label::Code);
+                    pending.push(target);
+                }
+                Instruction::Call(target) => {
+                    self.labels.insert(target, Label::Code);
+                    pending.push(target);
+                    pending.push(next);
+                }
+                Instruction::LoadIndex(target) => {
+                    self.labels.entry(target).or_insert(Label::Data);
+                    pending.push(next);
+                }
+                Instruction::LoadLongIndex => {
+                    let target = self.get_word(offset + 2).unwrap_or(0);
+                    self.labels.entry(target).or_insert(Label::Data);
+                    pending.push(next);
+                }
+                instruction if instruction.is_skip() => {
+                    pending.push(next);
+                    let skipped_size = self
+                        .get_offset(next)
+                        .and_then(|offset| self.decode_at(offset))
+                        .map_or(2, |instruction| instruction.size());
+                    pending.push(next.wrapping_add(skipped_size));
+                }
+                _ => pending.push(next),
+            }
+        }
+    }
+
+    /// Keeps only labels that start a line of the listing, the others are
+    /// printed as plain addresses.
+    fn drop_unreachable_labels(&mut self) {
+        let labels = std::mem::take(&mut self.labels);
+        self.labels = labels
+            .into_iter()
+            .filter_map(|(address, label)| {
+                let offset = self.get_offset(address)?;
+                if self.instructions.contains_key(&offset) {
+                    Some((address, Label::Code))
+                } else if !self.is_code[offset] {
+                    Some((address, label))
+                } else {
+                    None
+                }
+            })
+            .collect();
+    }
+
+    fn listing(&self) -> String {
+        let mut listing = String::new();
+        let _ = writeln!(listing, "; {} bytes", self.rom.len());
+        let _ = writeln!(listing, "    ORG {:#05X}", self.load_address);
+        let mut offset = 0;
+        let mut is_sprite = false;
+        while offset < self.rom.len() {
+            let address = self.get_address(offset);
+            if let Some(label) = self.labels.get(&address) {
+                let _ = writeln!(listing, "\n{}:", label_name(address, *label));
+                is_sprite = *label == Label::Data;
+            }
+            if let Some(instruction) = self.instructions.get(&offset) {
+                let size = instruction.size() as usize;
+                let mut text = instruction.format_with(|address| self.format_address(address));
+                if *instruction == Instruction::LoadLongIndex {
+                    let target = self.get_word(offset + 2).unwrap_or(0);
+                    let _ = write!(text, " {}", self.format_address(target));
+                }
+                self.write_line(&mut listing, &text, offset, size);
+                offset += size;
+                is_sprite = false;
+                continue;
+            }
+            let length = if is_sprite {
+                1
+            } else {
+                self.get_data_length(offset)
+            };
+            let bytes = &self.rom[offset..offset + length];
+            let values: Vec<String> = bytes.iter().map(|byte| format!("{byte:#04X}")).collect();
+            let text = format!("DB {}", values.join(", "));
+            if is_sprite {
+                let pixels: String = (0..8)
+                    .map(|bit| {
+                        if bytes[0] & (0x80 >> bit) != 0 {
+                            '#'
+                        } else {
+                            '.'
+                        }
+                    })
+                    .collect();
+                let _ = writeln!(
+                    listing,
+                    "    {text:<COMMENT_COLUMN$}; {address:03X}: {pixels}"
+                );
+            } else {
+                self.write_line(&mut listing, &text, offset, length);
+            }
+            offset += length;
+        }
+        listing
+    }
+
+    fn write_line(&self, listing: &mut String, text: &str, offset: usize, length: usize) {
+        let bytes: Vec<String> = self.rom[offset..offset + length]
+            .iter()
+            .map(|byte| format!("{byte:02X}"))
+            .collect();
+        let _ = writeln!(
+            listing,
+            "    {text:<COMMENT_COLUMN$}; {:03X}: {}",
+            self.get_address(offset),
+            bytes.join(" ")
+        );
+    }
+
+    /// Number of data bytes to put on one line, stopping before the next
+    /// instruction or label.
+    fn get_data_length(&self, offset: usize) -> usize {
+        let mut length = 1;
+        while length < DATA_BYTES_PER_LINE
+            && offset + length < self.rom.len()
+            && !self.is_code[offset + length]
+            && !self.labels.contains_key(&self.get_address(offset + length))
+        {
+            length += 1;
+        }
+        length
+    }
+
+    fn format_address(&self, address: u16) -> String {
+        match self.labels.get(&address) {
+            Some(label) => label_name(address, *label),
+            None => format!("{address:#05X}"),
+        }
+    }
+
+    fn decode_at(&self, offset: usize) -> Option<Instruction> {
+        let instruction = Instruction::decode(self.get_word(offset)?);
+        let is_complete = offset + instruction.size() as usize <= self.rom.len();
+        let is_known = !matches!(instruction, Instruction::Unknown(_));
+        (is_complete && is_known).then_some(instruction)
+    }
+
+    fn get_word(&self, offset: usize) -> Option<u16> {
+        let bytes = self.rom.get(offset..offset + 2)?;
+        Some((bytes[0] as u16) << 8 | bytes[1] as u16)
+    }
+
+    fn get_offset(&self, address: u16) -> Option<usize> {
+        let offset = address.checked_sub(self.load_address)? as usize;
+        (offset < self.rom.len()).then_some(offset)
+    }
+
+    fn get_address(&self, offset: usize) -> u16 {
+        self.load_address.wrapping_add(offset as u16)
+    }
+}
+
+fn label_name(address: u16, label: Label) -> String {
+    match label {
+        Label::Code => format!("L{address:03X}"),
+        Label::Data => format!("S{address:03X}"),
+    }
+}
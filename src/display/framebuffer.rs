@@ -0,0 +1,289 @@
+use super::Palette;
+use std::fmt;
+
+pub const WIDTH_PIXEL_COUNT: u8 = 64;
+pub const HEIGHT_PIXEL_COUNT: u8 = 32;
+pub const HIRES_WIDTH_PIXEL_COUNT: u8 = 128;
+pub const HIRES_HEIGHT_PIXEL_COUNT: u8 = 64;
+pub const PLANE_COUNT: usize = 4;
+
+/// The screen contents. Every pixel holds one bit per bitplane; plain CHIP-8
+/// and SUPER-CHIP programs only ever touch the first plane, XO-CHIP programs
+/// select planes with FN01.
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+    selected_planes: u8,
+    has_changed: bool,
+}
+
+impl Default for Framebuffer {
+    fn default() -> Self {
+        Self {
+            width: WIDTH_PIXEL_COUNT as usize,
+            height: HEIGHT_PIXEL_COUNT as usize,
+            pixels: vec![0; (WIDTH_PIXEL_COUNT as usize) * (HEIGHT_PIXEL_COUNT as usize)],
+            selected_planes: 1,
+            has_changed: false,
+        }
+    }
+}
+
+impl Framebuffer {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.width == HIRES_WIDTH_PIXEL_COUNT as usize
+    }
+
+    /// Switches between the 64x32 and the SUPER-CHIP 128x64 resolutions. The
+    /// screen is cleared either way.
+    pub fn set_hires(&mut self, is_hires: bool) {
+        let (width, height) = if is_hires {
+            (HIRES_WIDTH_PIXEL_COUNT, HIRES_HEIGHT_PIXEL_COUNT)
+        } else {
+            (WIDTH_PIXEL_COUNT, HEIGHT_PIXEL_COUNT)
+        };
+        self.width = width as usize;
+        self.height = height as usize;
+        self.pixels = vec![0; self.width * self.height];
+        self.has_changed = true;
+    }
+
+    /// Bitmask of the planes that drawing, clearing and scrolling act on.
+    pub fn selected_planes(&self) -> u8 {
+        self.selected_planes
+    }
+
+    pub fn select_planes(&mut self, planes: u8) {
+        self.selected_planes = planes & ((1 << PLANE_COUNT) - 1);
+    }
+
+    pub fn selected_plane_count(&self) -> usize {
+        self.selected_planes.count_ones() as usize
+    }
+
+    /// Every pixel as a bitmask of the planes it is lit in, row by row.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Overwrites every pixel, e.g. from a save state. `pixels` has to match
+    /// the current resolution.
+    pub fn set_pixels(&mut self, pixels: &[u8]) -> Result<(), String> {
+        if pixels.len() != self.pixels.len() {
+            return Err(format!(
+                "Expected {} pixels for a {}x{} screen, got {}",
+                self.pixels.len(),
+                self.width,
+                self.height,
+                pixels.len()
+            ));
+        }
+        self.pixels.copy_from_slice(pixels);
+        self.has_changed = true;
+        Ok(())
+    }
+
+    pub fn get_pixel(&self, x_position: usize, y_position: usize) -> bool {
+        self.get_pixel_planes(x_position, y_position) != 0
+    }
+
+    pub fn get_pixel_planes(&self, x_position: usize, y_position: usize) -> u8 {
+        if x_position < self.width && y_position < self.height {
+            self.pixels[x_position + y_position * self.width]
+        } else {
+            0
+        }
+    }
+
+    pub fn has_changed(&self) -> bool {
+        self.has_changed
+    }
+
+    pub fn mark_presented(&mut self) {
+        self.has_changed = false;
+    }
+
+    pub fn clear(&mut self) {
+        let planes = self.selected_planes;
+        self.pixels.iter_mut().for_each(|e| *e &= !planes);
+        self.has_changed = true;
+    }
+
+    /// XORs an 8 pixel wide sprite onto the screen and returns whether any
+    /// pixel was turned off.
+    pub fn draw(&mut self, x_position: u8, y_position: u8, bytes: &[u8], clip: bool) -> bool {
+        self.draw_sprite(x_position, y_position, bytes, 1, clip) > 0
+    }
+
+    /// XORs a sprite `bytes_per_row` bytes wide onto every selected plane,
+    /// taking the data for each plane from consecutive slices of `bytes`.
+    /// Pixels past the edges are dropped when `clip` is set and wrap around to
+    /// the other side otherwise. Returns how many rows turned off at least one
+    /// pixel.
+    pub fn draw_sprite(
+        &mut self,
+        x_position: u8,
+        y_position: u8,
+        bytes: &[u8],
+        bytes_per_row: usize,
+        clip: bool,
+    ) -> u8 {
+        let plane_count = self.selected_plane_count().max(1);
+        let sprite_size = bytes.len() / plane_count;
+        let mut collided_rows = vec![false; sprite_size / bytes_per_row.max(1) + 1];
+        let mut sprites = bytes.chunks(sprite_size.max(1));
+        for plane in 0..PLANE_COUNT {
+            let plane = 1 << plane;
+            if self.selected_planes & plane == 0 {
+                continue;
+            }
+            if let Some(sprite) = sprites.next() {
+                self.draw_plane(
+                    plane,
+                    x_position,
+                    y_position,
+                    sprite,
+                    bytes_per_row,
+                    clip,
+                    &mut collided_rows,
+                );
+            }
+        }
+        self.has_changed = true;
+        collided_rows.iter().filter(|collided| **collided).count() as u8
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn draw_plane(
+        &mut self,
+        plane: u8,
+        x_position: u8,
+        y_position: u8,
+        bytes: &[u8],
+        bytes_per_row: usize,
+        clip: bool,
+        collided_rows: &mut [bool],
+    ) {
+        let x_position = x_position as usize & (self.width - 1);
+        let y_position = y_position as usize & (self.height - 1);
+        for (row_number, row) in bytes.chunks(bytes_per_row).enumerate() {
+            let mut y_position = y_position + row_number;
+            if y_position >= self.height {
+                if clip {
+                    break;
+                }
+                y_position %= self.height;
+            }
+            for (byte_number, byte) in row.iter().enumerate() {
+                for bit in 0..8 {
+                    if byte & (0b10000000 >> bit) == 0 {
+                        continue;
+                    }
+                    let mut x_position = x_position + byte_number * 8 + bit;
+                    if x_position >= self.width {
+                        if clip {
+                            continue;
+                        }
+                        x_position %= self.width;
+                    }
+                    if self.flip_pixel(plane, x_position, y_position) {
+                        collided_rows[row_number] = true;
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn scroll_down(&mut self, lines: usize) {
+        let shift = lines.min(self.height) * self.width;
+        let mut scrolled = self.pixels.clone();
+        scrolled.rotate_right(shift);
+        scrolled[..shift].iter_mut().for_each(|e| *e = 0);
+        self.replace_selected_planes(&scrolled);
+    }
+
+    pub fn scroll_up(&mut self, lines: usize) {
+        let shift = lines.min(self.height) * self.width;
+        let mut scrolled = self.pixels.clone();
+        scrolled.rotate_left(shift);
+        let length = scrolled.len();
+        scrolled[length - shift..].iter_mut().for_each(|e| *e = 0);
+        self.replace_selected_planes(&scrolled);
+    }
+
+    pub fn scroll_right(&mut self, columns: usize) {
+        let columns = columns.min(self.width);
+        let mut scrolled = self.pixels.clone();
+        for row in scrolled.chunks_mut(self.width) {
+            row.rotate_right(columns);
+            row[..columns].iter_mut().for_each(|e| *e = 0);
+        }
+        self.replace_selected_planes(&scrolled);
+    }
+
+    pub fn scroll_left(&mut self, columns: usize) {
+        let columns = columns.min(self.width);
+        let mut scrolled = self.pixels.clone();
+        for row in scrolled.chunks_mut(self.width) {
+            row.rotate_left(columns);
+            let width = row.len();
+            row[width - columns..].iter_mut().for_each(|e| *e = 0);
+        }
+        self.replace_selected_planes(&scrolled);
+    }
+
+    /// Takes the selected planes from `pixels` and keeps the others as they are.
+    fn replace_selected_planes(&mut self, pixels: &[u8]) {
+        let planes = self.selected_planes;
+        for (pixel, new_pixel) in self.pixels.iter_mut().zip(pixels) {
+            *pixel = (*pixel & !planes) | (new_pixel & planes);
+        }
+        self.has_changed = true;
+    }
+
+    fn flip_pixel(&mut self, plane: u8, x_position: usize, y_position: usize) -> bool {
+        let position = x_position + y_position * self.width;
+        self.pixels[position] ^= plane;
+        self.pixels[position] & plane == 0
+    }
+}
+
+impl Framebuffer {
+    /// Encodes the screen as a binary PPM image, one image pixel per screen
+    /// pixel, in the colors of `palette`.
+    pub fn to_ppm(&self, palette: &Palette) -> Vec<u8> {
+        let mut image = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        for planes in &self.pixels {
+            let (red, green, blue) = palette.get_color(*planes);
+            image.extend([red, green, blue]);
+        }
+        image
+    }
+}
+
+impl fmt::Display for Framebuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for y_position in 0..self.height() {
+            let line: String = (0..self.width())
+                .map(
+                    |x_position| match self.get_pixel_planes(x_position, y_position) {
+                        0 => '.',
+                        1 => '#',
+                        planes => char::from_digit(planes as u32, 16).unwrap_or('#'),
+                    },
+                )
+                .collect();
+            writeln!(f, "{line}")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,27 @@
+use super::{Display, Framebuffer};
+
+/// Display that only keeps the pixels in memory, for running without a window.
+#[derive(Default)]
+pub struct HeadlessDisplay {
+    framebuffer: Framebuffer,
+}
+
+impl HeadlessDisplay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Display for HeadlessDisplay {
+    fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+
+    fn framebuffer_mut(&mut self) -> &mut Framebuffer {
+        &mut self.framebuffer
+    }
+
+    fn refresh(&mut self) {
+        self.framebuffer.mark_presented();
+    }
+}
@@ -0,0 +1,73 @@
+use super::PLANE_COUNT;
+use std::str::FromStr;
+
+/// Colors used to present the framebuffer, indexed by the bitmask of planes a
+/// pixel is lit in. Single plane programs only use the first two entries.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Palette {
+    colors: [(u8, u8, u8); 1 << PLANE_COUNT],
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self {
+            colors: [
+                (0x00, 0x00, 0x00),
+                (0xFF, 0xFF, 0xFF),
+                (0xAA, 0xAA, 0xAA),
+                (0x55, 0x55, 0x55),
+                (0xFF, 0x00, 0x00),
+                (0x00, 0xFF, 0x00),
+                (0x00, 0x00, 0xFF),
+                (0xFF, 0xFF, 0x00),
+                (0x88, 0x00, 0x00),
+                (0x00, 0x88, 0x00),
+                (0x00, 0x00, 0x88),
+                (0x88, 0x88, 0x00),
+                (0xFF, 0x00, 0xFF),
+                (0x00, 0xFF, 0xFF),
+                (0x88, 0x00, 0x88),
+                (0x00, 0x88, 0x88),
+            ],
+        }
+    }
+}
+
+impl Palette {
+    pub fn get_color(&self, planes: u8) -> (u8, u8, u8) {
+        self.colors[planes as usize % self.colors.len()]
+    }
+
+    pub fn set_color(&mut self, planes: u8, color: (u8, u8, u8)) {
+        let index = planes as usize % self.colors.len();
+        self.colors[index] = color;
+    }
+}
+
+/// Parses a comma separated list of `RRGGBB` colors, starting at the
+/// background. Colors that are left out keep their default.
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(colors: &str) -> Result<Self, Self::Err> {
+        let mut palette = Palette::default();
+        for (planes, color) in colors.split(',').enumerate() {
+            if planes >= palette.colors.len() {
+                return Err(format!(
+                    "A palette has at most {} colors",
+                    palette.colors.len()
+                ));
+            }
+            let color = color.trim().trim_start_matches('#');
+            let value = u32::from_str_radix(color, 16)
+                .ok()
+                .filter(|_| color.len() == 6)
+                .ok_or_else(|| format!("Invalid color {color}, expected RRGGBB"))?;
+            palette.set_color(
+                planes as u8,
+                ((value >> 16) as u8, (value >> 8) as u8, value as u8),
+            );
+        }
+        Ok(palette)
+    }
+}
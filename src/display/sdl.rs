@@ -0,0 +1,98 @@
+extern crate sdl2;
+
+use super::{Display, Framebuffer, Palette, HEIGHT_PIXEL_COUNT, PLANE_COUNT, WIDTH_PIXEL_COUNT};
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::WindowCanvas;
+use sdl2::video::FullscreenType;
+
+pub struct DisplayChip8 {
+    framebuffer: Framebuffer,
+    palette: Palette,
+    pub canvas: WindowCanvas,
+}
+
+impl DisplayChip8 {
+    pub fn new(pixel_size: u32) -> Self {
+        let sdl_context = sdl2::init().unwrap();
+        let video_subsystem = sdl_context.video().unwrap();
+        let window = video_subsystem
+            .window(
+                "CHIP8",
+                pixel_size * (WIDTH_PIXEL_COUNT as u32),
+                pixel_size * (HEIGHT_PIXEL_COUNT as u32),
+            )
+            .position_centered()
+            .build()
+            .unwrap();
+        let canvas = window.into_canvas().build().unwrap();
+        Self {
+            framebuffer: Framebuffer::default(),
+            palette: Palette::default(),
+            canvas,
+        }
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    /// Fills the desktop with the window, scaling the screen up to fit.
+    pub fn set_fullscreen(&mut self, is_fullscreen: bool) -> Result<(), String> {
+        let fullscreen_type = if is_fullscreen {
+            FullscreenType::Desktop
+        } else {
+            FullscreenType::Off
+        };
+        self.canvas.window_mut().set_fullscreen(fullscreen_type)
+    }
+
+    fn render(&mut self) -> Result<(), String> {
+        let (red, green, blue) = self.palette.get_color(0);
+        self.canvas.set_draw_color(Color::RGB(red, green, blue));
+        self.canvas.clear();
+        // Drawing at the resolution of the screen lets SDL scale it to the
+        // window, whatever the mode and the window size
+        let width = self.framebuffer.width() as u32;
+        let height = self.framebuffer.height() as u32;
+        if self.canvas.logical_size() != (width, height) {
+            self.canvas
+                .set_logical_size(width, height)
+                .map_err(|error| error.to_string())?;
+        }
+        let mut pixels_drawn: Vec<Vec<Rect>> = vec![Vec::new(); 1 << PLANE_COUNT];
+        for (position, planes) in self.framebuffer.pixels().iter().enumerate() {
+            if *planes != 0 {
+                let x = position as u32 % width;
+                let y = position as u32 / width;
+                pixels_drawn[*planes as usize].push(Rect::new(x as i32, y as i32, 1, 1));
+            }
+        }
+        for (planes, rects) in pixels_drawn.iter().enumerate() {
+            if !rects.is_empty() {
+                let (red, green, blue) = self.palette.get_color(planes as u8);
+                self.canvas.set_draw_color(Color::RGB(red, green, blue));
+                self.canvas.fill_rects(rects)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for DisplayChip8 {
+    fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+
+    fn framebuffer_mut(&mut self) -> &mut Framebuffer {
+        &mut self.framebuffer
+    }
+
+    fn refresh(&mut self) {
+        if self.framebuffer.has_changed() {
+            let _ = self.render();
+            self.canvas.present();
+            self.framebuffer.mark_presented();
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Something a program did that the machine can't carry out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Chip8Error {
+    /// The opcode doesn't exist on the current platform.
+    InvalidOpcode {
+        address: u16,
+        opcode: u16,
+    },
+    /// A 2NNN at `address` with every stack level in use.
+    StackOverflow {
+        address: u16,
+    },
+    /// A 00EE at `address` outside of any subroutine.
+    StackUnderflow {
+        address: u16,
+    },
+    /// An access past the end of memory.
+    MemoryOutOfRange {
+        address: usize,
+        size: usize,
+    },
+    RomTooLarge {
+        size: usize,
+        max_size: usize,
+    },
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidOpcode { address, opcode } => {
+                write!(f, "Invalid opcode {opcode:04X} at {address:#05X}")
+            }
+            Self::StackOverflow { address } => write!(f, "Stack overflow at {address:#05X}"),
+            Self::StackUnderflow { address } => {
+                write!(f, "Return without a subroutine at {address:#05X}")
+            }
+            Self::MemoryOutOfRange { address, size } => write!(
+                f,
+                "Memory access at {address:#05X} is out of range, memory ends at {size:#05X}"
+            ),
+            Self::RomTooLarge { size, max_size } => {
+                write!(f, "ROM is {size} bytes, at most {max_size} fit in memory")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Chip8Error {}
+
+/// Names accepted by [`ErrorPolicy::from_str`].
+pub const ERROR_POLICY_NAMES: [&str; 3] = ["ignore", "halt", "debug"];
+
+/// What happens when the program runs into a [`Chip8Error`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Report the error and carry on with the next instruction.
+    #[default]
+    Ignore,
+    /// Report the error with the machine state and stop.
+    Halt,
+    /// Pause in the debugger.
+    Debug,
+}
+
+impl FromStr for ErrorPolicy {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "ignore" => Ok(Self::Ignore),
+            "halt" => Ok(Self::Halt),
+            "debug" => Ok(Self::Debug),
+            _ => Err(format!(
+                "Unknown error policy {name}, expected one of: {}",
+                ERROR_POLICY_NAMES.join(", ")
+            )),
+        }
+    }
+}
@@ -0,0 +1,15 @@
+/// The keys held during one 60 Hz frame and how many times the CPU was
+/// stepped in it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub keys: [bool; 16],
+    pub instructions: u32,
+}
+
+/// Where the runner takes its key state from instead of the keyboard, e.g. a
+/// movie being replayed. Recorded input also fixes the number of
+/// instructions per frame, so playback doesn't depend on timing.
+pub trait Input {
+    /// The next frame to run, or `None` once the input is used up.
+    fn next_frame(&mut self) -> Option<Frame>;
+}
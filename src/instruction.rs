@@ -0,0 +1,421 @@
+use crate::platform::Platform;
+use std::fmt;
+
+/// A decoded opcode with its operands extracted. Covers CHIP-8, SUPER-CHIP
+/// and XO-CHIP; use [`Instruction::is_supported_on`] to check whether a
+/// platform understands it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    /// 00E0
+    ClearScreen,
+    /// 00EE
+    Return,
+    /// 00CN
+    ScrollDown(u8),
+    /// 00DN
+    ScrollUp(u8),
+    /// 00FB
+    ScrollRight,
+    /// 00FC
+    ScrollLeft,
+    /// 00FD
+    Exit,
+    /// 00FE
+    LowRes,
+    /// 00FF
+    HighRes,
+    /// 1NNN
+    Jump(u16),
+    /// 2NNN
+    Call(u16),
+    /// 3XNN
+    SkipIfEqual {
+        x: u8,
+        value: u8,
+    },
+    /// 4XNN
+    SkipIfNotEqual {
+        x: u8,
+        value: u8,
+    },
+    /// 5XY0
+    SkipIfRegistersEqual {
+        x: u8,
+        y: u8,
+    },
+    /// 5XY2
+    SaveRange {
+        x: u8,
+        y: u8,
+    },
+    /// 5XY3
+    LoadRange {
+        x: u8,
+        y: u8,
+    },
+    /// 6XNN
+    Load {
+        x: u8,
+        value: u8,
+    },
+    /// 7XNN
+    Add {
+        x: u8,
+        value: u8,
+    },
+    /// 8XY0
+    Move {
+        x: u8,
+        y: u8,
+    },
+    /// 8XY1
+    Or {
+        x: u8,
+        y: u8,
+    },
+    /// 8XY2
+    And {
+        x: u8,
+        y: u8,
+    },
+    /// 8XY3
+    Xor {
+        x: u8,
+        y: u8,
+    },
+    /// 8XY4
+    AddRegisters {
+        x: u8,
+        y: u8,
+    },
+    /// 8XY5
+    Subtract {
+        x: u8,
+        y: u8,
+    },
+    /// 8XY6
+    ShiftRight {
+        x: u8,
+        y: u8,
+    },
+    /// 8XY7
+    SubtractReversed {
+        x: u8,
+        y: u8,
+    },
+    /// 8XYE
+    ShiftLeft {
+        x: u8,
+        y: u8,
+    },
+    /// 9XY0
+    SkipIfRegistersNotEqual {
+        x: u8,
+        y: u8,
+    },
+    /// ANNN
+    LoadIndex(u16),
+    /// BNNN, or BXNN with the `jump_uses_vx` quirk.
+    JumpOffset {
+        address: u16,
+        x: u8,
+    },
+    /// CXNN
+    Random {
+        x: u8,
+        mask: u8,
+    },
+    /// DXYN
+    Draw {
+        x: u8,
+        y: u8,
+        rows: u8,
+    },
+    /// EX9E
+    SkipIfKey(u8),
+    /// EXA1
+    SkipIfNotKey(u8),
+    /// F000 NNNN, the address is the word following the opcode.
+    LoadLongIndex,
+    /// FN01
+    SelectPlanes(u8),
+    /// F002
+    LoadAudioPattern,
+    /// FX07
+    GetDelayTimer(u8),
+    /// FX0A
+    WaitKey(u8),
+    /// FX15
+    SetDelayTimer(u8),
+    /// FX18
+    SetSoundTimer(u8),
+    /// FX1E
+    AddToIndex(u8),
+    /// FX29
+    LoadFont(u8),
+    /// FX30
+    LoadBigFont(u8),
+    /// FX33
+    StoreBcd(u8),
+    /// FX3A
+    SetPitch(u8),
+    /// FX55
+    StoreRegisters(u8),
+    /// FX65
+    LoadRegisters(u8),
+    /// FX75
+    StoreFlags(u8),
+    /// FX85
+    LoadFlags(u8),
+    Unknown(u16),
+}
+
+impl Instruction {
+    pub fn decode(opcode: u16) -> Self {
+        let first_nibble = (opcode & 0xF000) >> 12;
+        let x = ((opcode & 0x0F00) >> 8) as u8;
+        let y = ((opcode & 0x00F0) >> 4) as u8;
+        let n = (opcode & 0x000F) as u8;
+        let byte = (opcode & 0x00FF) as u8;
+        let address = opcode & 0x0FFF;
+        match (first_nibble, x, y, n) {
+            (0x0, 0x0, 0xE, 0x0) => Instruction::ClearScreen,
+            (0x0, 0x0, 0xE, 0xE) => Instruction::Return,
+            (0x0, 0x0, 0xC, _) => Instruction::ScrollDown(n),
+            (0x0, 0x0, 0xD, _) => Instruction::ScrollUp(n),
+            (0x0, 0x0, 0xF, 0xB) => Instruction::ScrollRight,
+            (0x0, 0x0, 0xF, 0xC) => Instruction::ScrollLeft,
+            (0x0, 0x0, 0xF, 0xD) => Instruction::Exit,
+            (0x0, 0x0, 0xF, 0xE) => Instruction::LowRes,
+            (0x0, 0x0, 0xF, 0xF) => Instruction::HighRes,
+            (0x1, _, _, _) => Instruction::Jump(address),
+            (0x2, _, _, _) => Instruction::Call(address),
+            (0x3, _, _, _) => Instruction::SkipIfEqual { x, value: byte },
+            (0x4, _, _, _) => Instruction::SkipIfNotEqual { x, value: byte },
+            (0x5, _, _, 0x0) => Instruction::SkipIfRegistersEqual { x, y },
+            (0x5, _, _, 0x2) => Instruction::SaveRange { x, y },
+            (0x5, _, _, 0x3) => Instruction::LoadRange { x, y },
+            (0x6, _, _, _) => Instruction::Load { x, value: byte },
+            (0x7, _, _, _) => Instruction::Add { x, value: byte },
+            (0x8, _, _, 0x0) => Instruction::Move { x, y },
+            (0x8, _, _, 0x1) => Instruction::Or { x, y },
+            (0x8, _, _, 0x2) => Instruction::And { x, y },
+            (0x8, _, _, 0x3) => Instruction::Xor { x, y },
+            (0x8, _, _, 0x4) => Instruction::AddRegisters { x, y },
+            (0x8, _, _, 0x5) => Instruction::Subtract { x, y },
+            (0x8, _, _, 0x6) => Instruction::ShiftRight { x, y },
+            (0x8, _, _, 0x7) => Instruction::SubtractReversed { x, y },
+            (0x8, _, _, 0xE) => Instruction::ShiftLeft { x, y },
+            (0x9, _, _, 0x0) => Instruction::SkipIfRegistersNotEqual { x, y },
+            (0xA, _, _, _) => Instruction::LoadIndex(address),
+            (0xB, _, _, _) => Instruction::JumpOffset { address, x },
+            (0xC, _, _, _) => Instruction::Random { x, mask: byte },
+            (0xD, _, _, _) => Instruction::Draw { x, y, rows: n },
+            (0xE, _, 0x9, 0xE) => Instruction::SkipIfKey(x),
+            (0xE, _, 0xA, 0x1) => Instruction::SkipIfNotKey(x),
+            (0xF, 0x0, 0x0, 0x0) => Instruction::LoadLongIndex,
+            (0xF, _, 0x0, 0x1) => Instruction::SelectPlanes(x),
+            (0xF, 0x0, 0x0, 0x2) => Instruction::LoadAudioPattern,
+            (0xF, _, 0x0, 0x7) => Instruction::GetDelayTimer(x),
+            (0xF, _, 0x0, 0xA) => Instruction::WaitKey(x),
+            (0xF, _, 0x1, 0x5) => Instruction::SetDelayTimer(x),
+            (0xF, _, 0x1, 0x8) => Instruction::SetSoundTimer(x),
+            (0xF, _, 0x1, 0xE) => Instruction::AddToIndex(x),
+            (0xF, _, 0x2, 0x9) => Instruction::LoadFont(x),
+            (0xF, _, 0x3, 0x0) => Instruction::LoadBigFont(x),
+            (0xF, _, 0x3, 0x3) => Instruction::StoreBcd(x),
+            (0xF, _, 0x3, 0xA) => Instruction::SetPitch(x),
+            (0xF, _, 0x5, 0x5) => Instruction::StoreRegisters(x),
+            (0xF, _, 0x6, 0x5) => Instruction::LoadRegisters(x),
+            (0xF, _, 0x7, 0x5) => Instruction::StoreFlags(x),
+            (0xF, _, 0x8, 0x5) => Instruction::LoadFlags(x),
+            _ => Instruction::Unknown(opcode),
+        }
+    }
+
+    /// The opcode this instruction decodes from. For F000 the address word
+    /// has to be written separately.
+    pub fn encode(&self) -> u16 {
+        let with_x = |base: u16, x: u8| base | (x as u16 & 0xF) << 8;
+        let with_xy = |base: u16, x: u8, y: u8| with_x(base, x) | (y as u16 & 0xF) << 4;
+        let with_byte = |base: u16, x: u8, byte: u8| with_x(base, x) | byte as u16;
+        match *self {
+            Instruction::ClearScreen => 0x00E0,
+            Instruction::Return => 0x00EE,
+            Instruction::ScrollDown(n) => 0x00C0 | (n as u16 & 0xF),
+            Instruction::ScrollUp(n) => 0x00D0 | (n as u16 & 0xF),
+            Instruction::ScrollRight => 0x00FB,
+            Instruction::ScrollLeft => 0x00FC,
+            Instruction::Exit => 0x00FD,
+            Instruction::LowRes => 0x00FE,
+            Instruction::HighRes => 0x00FF,
+            Instruction::Jump(address) => 0x1000 | (address & 0xFFF),
+            Instruction::Call(address) => 0x2000 | (address & 0xFFF),
+            Instruction::SkipIfEqual { x, value } => with_byte(0x3000, x, value),
+            Instruction::SkipIfNotEqual { x, value } => with_byte(0x4000, x, value),
+            Instruction::SkipIfRegistersEqual { x, y } => with_xy(0x5000, x, y),
+            Instruction::SaveRange { x, y } => with_xy(0x5002, x, y),
+            Instruction::LoadRange { x, y } => with_xy(0x5003, x, y),
+            Instruction::Load { x, value } => with_byte(0x6000, x, value),
+            Instruction::Add { x, value } => with_byte(0x7000, x, value),
+            Instruction::Move { x, y } => with_xy(0x8000, x, y),
+            Instruction::Or { x, y } => with_xy(0x8001, x, y),
+            Instruction::And { x, y } => with_xy(0x8002, x, y),
+            Instruction::Xor { x, y } => with_xy(0x8003, x, y),
+            Instruction::AddRegisters { x, y } => with_xy(0x8004, x, y),
+            Instruction::Subtract { x, y } => with_xy(0x8005, x, y),
+            Instruction::ShiftRight { x, y } => with_xy(0x8006, x, y),
+            Instruction::SubtractReversed { x, y } => with_xy(0x8007, x, y),
+            Instruction::ShiftLeft { x, y } => with_xy(0x800E, x, y),
+            Instruction::SkipIfRegistersNotEqual { x, y } => with_xy(0x9000, x, y),
+            Instruction::LoadIndex(address) => 0xA000 | (address & 0xFFF),
+            Instruction::JumpOffset { address, .. } => 0xB000 | (address & 0xFFF),
+            Instruction::Random { x, mask } => with_byte(0xC000, x, mask),
+            Instruction::Draw { x, y, rows } => with_xy(0xD000, x, y) | (rows as u16 & 0xF),
+            Instruction::SkipIfKey(x) => with_x(0xE09E, x),
+            Instruction::SkipIfNotKey(x) => with_x(0xE0A1, x),
+            Instruction::LoadLongIndex => 0xF000,
+            Instruction::SelectPlanes(planes) => with_x(0xF001, planes),
+            Instruction::LoadAudioPattern => 0xF002,
+            Instruction::GetDelayTimer(x) => with_x(0xF007, x),
+            Instruction::WaitKey(x) => with_x(0xF00A, x),
+            Instruction::SetDelayTimer(x) => with_x(0xF015, x),
+            Instruction::SetSoundTimer(x) => with_x(0xF018, x),
+            Instruction::AddToIndex(x) => with_x(0xF01E, x),
+            Instruction::LoadFont(x) => with_x(0xF029, x),
+            Instruction::LoadBigFont(x) => with_x(0xF030, x),
+            Instruction::StoreBcd(x) => with_x(0xF033, x),
+            Instruction::SetPitch(x) => with_x(0xF03A, x),
+            Instruction::StoreRegisters(x) => with_x(0xF055, x),
+            Instruction::LoadRegisters(x) => with_x(0xF065, x),
+            Instruction::StoreFlags(x) => with_x(0xF075, x),
+            Instruction::LoadFlags(x) => with_x(0xF085, x),
+            Instruction::Unknown(opcode) => opcode,
+        }
+    }
+
+    pub fn is_supported_on(&self, platform: Platform) -> bool {
+        match self {
+            Instruction::ScrollDown(_)
+            | Instruction::ScrollRight
+            | Instruction::ScrollLeft
+            | Instruction::Exit
+            | Instruction::LowRes
+            | Instruction::HighRes
+            | Instruction::LoadBigFont(_)
+            | Instruction::StoreFlags(_)
+            | Instruction::LoadFlags(_) => platform.has_superchip_instructions(),
+            Instruction::ScrollUp(_)
+            | Instruction::SaveRange { .. }
+            | Instruction::LoadRange { .. }
+            | Instruction::LoadLongIndex
+            | Instruction::SelectPlanes(_)
+            | Instruction::LoadAudioPattern
+            | Instruction::SetPitch(_) => platform.has_xochip_instructions(),
+            Instruction::Unknown(_) => false,
+            _ => true,
+        }
+    }
+
+    /// Size in bytes, counting the address word that follows F000.
+    pub fn size(&self) -> u16 {
+        match self {
+            Instruction::LoadLongIndex => 4,
+            _ => 2,
+        }
+    }
+
+    /// Address the instruction jumps to, calls or points I at, if it has one.
+    pub fn target_address(&self) -> Option<u16> {
+        match self {
+            Instruction::Jump(address)
+            | Instruction::Call(address)
+            | Instruction::LoadIndex(address)
+            | Instruction::JumpOffset { address, .. } => Some(*address),
+            _ => None,
+        }
+    }
+
+    pub fn is_skip(&self) -> bool {
+        matches!(
+            self,
+            Instruction::SkipIfEqual { .. }
+                | Instruction::SkipIfNotEqual { .. }
+                | Instruction::SkipIfRegistersEqual { .. }
+                | Instruction::SkipIfRegistersNotEqual { .. }
+                | Instruction::SkipIfKey(_)
+                | Instruction::SkipIfNotKey(_)
+        )
+    }
+
+    /// Formats the instruction, printing addresses with `format_address` so a
+    /// disassembler can substitute labels.
+    pub fn format_with(&self, format_address: impl Fn(u16) -> String) -> String {
+        match self {
+            Instruction::ClearScreen => String::from("CLS"),
+            Instruction::Return => String::from("RET"),
+            Instruction::ScrollDown(n) => format!("SCD {n}"),
+            Instruction::ScrollUp(n) => format!("SCU {n}"),
+            Instruction::ScrollRight => String::from("SCR"),
+            Instruction::ScrollLeft => String::from("SCL"),
+            Instruction::Exit => String::from("EXIT"),
+            Instruction::LowRes => String::from("LOW"),
+            Instruction::HighRes => String::from("HIGH"),
+            Instruction::Jump(address) => format!("JP {}", format_address(*address)),
+            Instruction::Call(address) => format!("CALL {}", format_address(*address)),
+            Instruction::SkipIfEqual { x, value } => format!("SE V{x:X}, {value:#04X}"),
+            Instruction::SkipIfNotEqual { x, value } => format!("SNE V{x:X}, {value:#04X}"),
+            Instruction::SkipIfRegistersEqual { x, y } => format!("SE V{x:X}, V{y:X}"),
+            Instruction::SaveRange { x, y } => format!("SAVE V{x:X}, V{y:X}"),
+            Instruction::LoadRange { x, y } => format!("LOAD V{x:X}, V{y:X}"),
+            Instruction::Load { x, value } => format!("LD V{x:X}, {value:#04X}"),
+            Instruction::Add { x, value } => format!("ADD V{x:X}, {value:#04X}"),
+            Instruction::Move { x, y } => format!("LD V{x:X}, V{y:X}"),
+            Instruction::Or { x, y } => format!("OR V{x:X}, V{y:X}"),
+            Instruction::And { x, y } => format!("AND V{x:X}, V{y:X}"),
+            Instruction::Xor { x, y } => format!("XOR V{x:X}, V{y:X}"),
+            Instruction::AddRegisters { x, y } => format!("ADD V{x:X}, V{y:X}"),
+            Instruction::Subtract { x, y } => format!("SUB V{x:X}, V{y:X}"),
+            Instruction::ShiftRight { x, y } => format!("SHR V{x:X}, V{y:X}"),
+            Instruction::SubtractReversed { x, y } => format!("SUBN V{x:X}, V{y:X}"),
+            Instruction::ShiftLeft { x, y } => format!("SHL V{x:X}, V{y:X}"),
+            Instruction::SkipIfRegistersNotEqual { x, y } => format!("SNE V{x:X}, V{y:X}"),
+            Instruction::LoadIndex(address) => format!("LD I, {}", format_address(*address)),
+            Instruction::JumpOffset { address, .. } => {
+                format!("JP V0, {}", format_address(*address))
+            }
+            Instruction::Random { x, mask } => format!("RND V{x:X}, {mask:#04X}"),
+            Instruction::Draw { x, y, rows } => format!("DRW V{x:X}, V{y:X}, {rows}"),
+            Instruction::SkipIfKey(x) => format!("SKP V{x:X}"),
+            Instruction::SkipIfNotKey(x) => format!("SKNP V{x:X}"),
+            Instruction::LoadLongIndex => String::from("LD I, LONG"),
+            Instruction::SelectPlanes(planes) => format!("PLANE {planes}"),
+            Instruction::LoadAudioPattern => String::from("AUDIO"),
+            Instruction::GetDelayTimer(x) => format!("LD V{x:X}, DT"),
+            Instruction::WaitKey(x) => format!("LD V{x:X}, K"),
+            Instruction::SetDelayTimer(x) => format!("LD DT, V{x:X}"),
+            Instruction::SetSoundTimer(x) => format!("LD ST, V{x:X}"),
+            Instruction::AddToIndex(x) => format!("ADD I, V{x:X}"),
+            Instruction::LoadFont(x) => format!("LD F, V{x:X}"),
+            Instruction::LoadBigFont(x) => format!("LD HF, V{x:X}"),
+            Instruction::StoreBcd(x) => format!("LD B, V{x:X}"),
+            Instruction::SetPitch(x) => format!("PITCH V{x:X}"),
+            Instruction::StoreRegisters(x) => format!("LD [I], V{x:X}"),
+            Instruction::LoadRegisters(x) => format!("LD V{x:X}, [I]"),
+            Instruction::StoreFlags(x) => format!("LD R, V{x:X}"),
+            Instruction::LoadFlags(x) => format!("LD V{x:X}, R"),
+            Instruction::Unknown(opcode) => format!("DW {opcode:#06X}"),
+        }
+    }
+}
+
+/// Classic mnemonic syntax, e.g. `LD V0, 0x10` or `DRW V1, V2, 5`.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            self.format_with(|address| format!("{address:#05X}"))
+        )
+    }
+}
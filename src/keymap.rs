@@ -0,0 +1,200 @@
+/// How far a stick has to be pushed, as a fraction of its range, before it
+/// presses a key.
+pub const DEFAULT_DEADZONE: f32 = 0.25;
+
+/// Which physical keys press each CHIP-8 key. Keys are SDL scancode names
+/// ("Q", "Up", "Keypad 5", ...), so the layout stays the same whatever the
+/// keyboard language. Game controller inputs are SDL button names ("a",
+/// "dpup", ...) or an axis name with the direction that presses the key
+/// ("leftx-", "lefty+", ...).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Keymap {
+    keys: [Vec<String>; 16],
+    controller_keys: [Vec<String>; 16],
+    deadzone: f32,
+}
+
+impl Default for Keymap {
+    /// The 1234/QWER/ASDF/ZXCV block laid out like the COSMAC VIP keypad.
+    fn default() -> Self {
+        let mut keymap = Self {
+            keys: Default::default(),
+            controller_keys: Default::default(),
+            deadzone: DEFAULT_DEADZONE,
+        };
+        let layout = [
+            ("1", 0x1),
+            ("2", 0x2),
+            ("3", 0x3),
+            ("4", 0xC),
+            ("Q", 0x4),
+            ("W", 0x5),
+            ("E", 0x6),
+            ("R", 0xD),
+            ("A", 0x7),
+            ("S", 0x8),
+            ("D", 0x9),
+            ("F", 0xE),
+            ("Z", 0xA),
+            ("X", 0x0),
+            ("C", 0xB),
+            ("V", 0xF),
+        ];
+        for (name, key) in layout {
+            keymap.keys[key].push(String::from(name));
+        }
+        // Directions on 2/4/6/8 like most games, with the face buttons around 5
+        let controller_layout = [
+            ("dpup", 0x2),
+            ("lefty-", 0x2),
+            ("dpleft", 0x4),
+            ("leftx-", 0x4),
+            ("dpright", 0x6),
+            ("leftx+", 0x6),
+            ("dpdown", 0x8),
+            ("lefty+", 0x8),
+            ("a", 0x5),
+            ("b", 0x9),
+            ("x", 0x7),
+            ("y", 0x1),
+            ("start", 0xF),
+            ("back", 0xE),
+        ];
+        for (name, key) in controller_layout {
+            keymap.controller_keys[key].push(String::from(name));
+        }
+        keymap
+    }
+}
+
+impl Keymap {
+    /// The names of the keys bound to a CHIP-8 key, none for keys past F.
+    pub fn get_bindings(&self, key: u8) -> &[String] {
+        self.keys.get(key as usize).map_or(&[], Vec::as_slice)
+    }
+
+    /// Replaces the keys bound to a CHIP-8 key.
+    pub fn set_bindings(&mut self, key: u8, names: Vec<String>) -> Result<(), String> {
+        *get_key_bindings(&mut self.keys, key)? = names;
+        Ok(())
+    }
+
+    /// Every binding as (key name, CHIP-8 key).
+    pub fn bindings(&self) -> impl Iterator<Item = (&str, u8)> {
+        flatten_bindings(&self.keys)
+    }
+
+    pub fn get_controller_bindings(&self, key: u8) -> &[String] {
+        self.controller_keys
+            .get(key as usize)
+            .map_or(&[], Vec::as_slice)
+    }
+
+    pub fn set_controller_bindings(&mut self, key: u8, names: Vec<String>) -> Result<(), String> {
+        *get_key_bindings(&mut self.controller_keys, key)? = names;
+        Ok(())
+    }
+
+    /// Every controller binding as (button or axis name, CHIP-8 key).
+    pub fn controller_bindings(&self) -> impl Iterator<Item = (&str, u8)> {
+        flatten_bindings(&self.controller_keys)
+    }
+
+    pub fn get_deadzone(&self) -> f32 {
+        self.deadzone
+    }
+
+    pub fn set_deadzone(&mut self, deadzone: f32) {
+        self.deadzone = deadzone;
+    }
+}
+
+fn get_key_bindings(keys: &mut [Vec<String>; 16], key: u8) -> Result<&mut Vec<String>, String> {
+    keys.get_mut(key as usize)
+        .ok_or_else(|| format!("Invalid key {key:X}, expected 0 to F"))
+}
+
+fn flatten_bindings(keys: &[Vec<String>; 16]) -> impl Iterator<Item = (&str, u8)> {
+    keys.iter()
+        .enumerate()
+        .flat_map(|(key, names)| names.iter().map(move |name| (name.as_str(), key as u8)))
+}
+
+/// Parses a comma separated list of `KEY=NAME` bindings such as `5=W,5=Up`
+/// into the names bound to each listed key. A binding given twice counts once.
+pub fn parse_bindings(text: &str) -> Result<Vec<(u8, Vec<String>)>, String> {
+    let mut bindings: Vec<(u8, Vec<String>)> = Vec::new();
+    for binding in text.split(',') {
+        let (key, name) = binding
+            .split_once('=')
+            .ok_or_else(|| format!("Invalid binding {binding}, expected KEY=NAME"))?;
+        let key = parse_key(key.trim())
+            .ok_or_else(|| format!("Invalid key {key}, expected a hexadecimal digit"))?;
+        let name = String::from(name.trim());
+        if name.is_empty() {
+            return Err(format!("Invalid binding {binding}, expected KEY=NAME"));
+        }
+        match bindings.iter_mut().find(|(bound_key, _)| *bound_key == key) {
+            Some((_, names)) if names.contains(&name) => (),
+            Some((_, names)) => names.push(name),
+            None => bindings.push((key, vec![name])),
+        }
+    }
+    Ok(bindings)
+}
+
+/// Parses a CHIP-8 key written as one hexadecimal digit.
+pub fn parse_key(text: &str) -> Option<u8> {
+    if text.len() != 1 {
+        return None;
+    }
+    u8::from_str_radix(text, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_keys() {
+        assert_eq!(parse_key("0"), Some(0));
+        assert_eq!(parse_key("f"), Some(0xF));
+        assert_eq!(parse_key("G"), None);
+        assert_eq!(parse_key("10"), None);
+        assert_eq!(parse_key(""), None);
+    }
+
+    #[test]
+    fn adds_the_names_given_for_the_same_key() {
+        assert_eq!(
+            parse_bindings("5=W, 5=Up,8=S,5=W"),
+            Ok(vec![
+                (5, vec![String::from("W"), String::from("Up")]),
+                (8, vec![String::from("S")])
+            ])
+        );
+    }
+
+    #[test]
+    fn rejects_bad_bindings() {
+        assert_eq!(
+            parse_bindings("G=W"),
+            Err(String::from("Invalid key G, expected a hexadecimal digit"))
+        );
+        assert_eq!(
+            parse_bindings("5=W,8"),
+            Err(String::from("Invalid binding 8, expected KEY=NAME"))
+        );
+        assert!(parse_bindings("5=").is_err());
+        assert!(parse_bindings("").is_err());
+    }
+
+    #[test]
+    fn rejects_keys_past_f() {
+        let mut keymap = Keymap::default();
+        assert!(keymap.set_bindings(0x10, vec![String::from("W")]).is_err());
+        assert!(keymap.set_controller_bindings(0x10, Vec::new()).is_err());
+        assert!(keymap.get_bindings(0x10).is_empty());
+        assert_eq!(keymap, Keymap::default());
+    }
+}
@@ -0,0 +1,28 @@
+//! CHIP-8 interpreter core.
+//!
+//! The [`CPU`] runs against any [`display::Display`], so the interpreter can be
+//! embedded or driven headlessly. The SDL window, audio and keyboard live in
+//! [`runner`], behind the `sdl` feature.
+
+pub mod assembler;
+mod binary;
+pub mod clock;
+pub mod config;
+pub mod cpu;
+pub mod debugger;
+pub mod disassembler;
+pub mod display;
+pub mod error;
+pub mod input;
+pub mod instruction;
+pub mod keymap;
+pub mod movie;
+pub mod platform;
+pub mod quirks;
+pub mod rewind;
+#[cfg(feature = "sdl")]
+pub mod runner;
+pub mod scheduler;
+pub mod sha1;
+
+pub use cpu::CPU;
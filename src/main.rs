@@ -1,26 +1,517 @@
-mod cpu;
-mod display;
+mod cli;
 
-use cpu::CPU;
+use chip_8::assembler;
+use chip_8::clock::{DEFAULT_INSTRUCTIONS_PER_FRAME, FRAMES_PER_SECOND};
+use chip_8::config::{Config, RomId};
+use chip_8::cpu::{Random, INITIAL_POSITION, VIP_STACK_ADDRESS};
+use chip_8::debugger::{self, Debugger, Report};
+use chip_8::disassembler;
+use chip_8::display::{Display, Framebuffer, Palette};
+use chip_8::error::{Chip8Error, ErrorPolicy};
+use chip_8::input::{Frame, Input};
+use chip_8::keymap::Keymap;
+use chip_8::movie::Movie;
+use chip_8::platform::Platform;
+use chip_8::quirks::Quirks;
+#[cfg(feature = "sdl")]
+use chip_8::rewind::{Rewind, DEFAULT_REWIND_SECONDS};
+use chip_8::scheduler::{Scheduler, Timing};
+use chip_8::CPU;
+use cli::{Command, HELP};
 use std::env;
 use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Instant;
+
+/// ROM name that reads the ROM from stdin instead of a file.
+const STDIN_ROM_NAME: &str = "-";
+/// Window pixels per CHIP-8 pixel.
+#[cfg(feature = "sdl")]
+const DEFAULT_SCALE: u32 = 20;
+/// Frames `test` and `bench` run, ten seconds.
+const DEFAULT_TEST_FRAMES: u32 = 600;
+
+/// Options that shape how the CPU behaves, shared by every way of running it.
+#[derive(Default)]
+struct Settings {
+    platform: Option<Platform>,
+    quirks: Option<Quirks>,
+    palette: Option<Palette>,
+    debugger: Option<Debugger>,
+    /// Seconds of history kept for rewinding, 0 turns it off.
+    rewind_seconds: Option<usize>,
+    /// Seed for CXNN, random on every run when not set.
+    seed: Option<u64>,
+    /// Where to save a movie of the session.
+    record_path: Option<PathBuf>,
+    /// Movie to replay instead of reading the keyboard.
+    replay: Option<Movie>,
+    config: Config,
+    instructions_per_frame: Option<u32>,
+    timing: Option<Timing>,
+    error_policy: ErrorPolicy,
+    stack_depth: Option<usize>,
+    /// Keep the stack in emulated memory like the VIP.
+    has_memory_stack: bool,
+    /// Where the ROM goes and execution starts, 0x200 when not set.
+    load_address: Option<u16>,
+    /// Bindings given on the command line, on top of those of the config.
+    key_bindings: Vec<(u8, Vec<String>)>,
+    /// The bindings of the config and of the command line.
+    keymap: Keymap,
+    /// Window pixels per CHIP-8 pixel.
+    scale: Option<u32>,
+    is_fullscreen: bool,
+    /// Buzzer amplitude from 0 to 1.
+    volume: Option<f32>,
+    /// Frames to run without a window.
+    frames: Option<u32>,
+    /// Where to save the final screen.
+    screenshot_path: Option<PathBuf>,
+}
+
+impl Settings {
+    /// Reads the ROM and fills in what wasn't given on the command line from
+    /// the config, using the ROM's own profile when it has one.
+    fn prepare(&mut self, rom_name: &str) -> Vec<u8> {
+        if rom_name == STDIN_ROM_NAME && self.debugger.is_some() {
+            exit_with_error(
+                "The debugger reads its commands from stdin, so the ROM can't come from there",
+            );
+        }
+        let rom_data = read_rom(rom_name);
+        let rom = RomId::new(rom_name, &rom_data);
+        let config = &self.config;
+        self.instructions_per_frame = self
+            .instructions_per_frame
+            .or_else(|| config.get_instructions_per_frame(&rom));
+        self.timing = self.timing.or_else(|| config.get_timing(&rom));
+        self.platform = self.platform.or_else(|| config.get_platform(&rom));
+        self.quirks = self.quirks.or_else(|| config.get_quirks(&rom));
+        self.palette = self.palette.or_else(|| config.get_palette(&rom));
+        self.seed = self.seed.or_else(|| config.get_seed(&rom));
+        self.keymap = config.get_keymap(&rom);
+        for (key, names) in &self.key_bindings {
+            if let Err(error) = self.keymap.set_bindings(*key, names.clone()) {
+                exit_with_error(&error);
+            }
+        }
+        if let Some(movie) = &self.replay {
+            if let Err(error) = movie.check_rom(&rom_data) {
+                exit_with_error(&error);
+            }
+        }
+        if self.record_path.is_some() {
+            // Replays need to know the seed, so pick one if none was given
+            let replay_seed = self.replay.as_ref().map(|movie| movie.seed);
+            let seed = replay_seed.or(self.seed);
+            self.seed = Some(seed.unwrap_or_else(|| Random::default().next_u64()));
+        }
+        rom_data
+    }
+
+    fn configure<D: Display>(&self, cpu: &mut CPU<D>) {
+        let platform = self.platform.unwrap_or_default();
+        cpu.set_platform(platform);
+        cpu.set_quirks(self.quirks.unwrap_or_else(|| platform.default_quirks()));
+        if let Some(seed) = self.seed {
+            cpu.set_seed(seed);
+        }
+        if let Some(stack_depth) = self.stack_depth {
+            cpu.set_stack_depth(stack_depth);
+        }
+        if self.has_memory_stack {
+            cpu.set_stack_address(Some(VIP_STACK_ADDRESS));
+        }
+        if let Some(movie) = &self.replay {
+            movie.configure(cpu);
+        }
+    }
+
+    fn load_rom<D: Display>(&self, cpu: &mut CPU<D>, rom_data: &[u8]) {
+        let address = self.get_load_address();
+        if let Err(error) = cpu.load_rom_at(rom_data, address) {
+            exit_with_error(&format!("Can't load the ROM at {address:#05X}: {error}"));
+        }
+    }
+
+    /// Where the ROM goes, which a replay takes from the movie.
+    fn get_load_address(&self) -> u16 {
+        match &self.replay {
+            Some(movie) => movie.load_address,
+            None => self.load_address.unwrap_or(INITIAL_POSITION as u16),
+        }
+    }
+
+    fn scheduler(&self) -> Scheduler {
+        Scheduler::new(
+            self.timing.unwrap_or_default(),
+            self.instructions_per_frame
+                .unwrap_or(DEFAULT_INSTRUCTIONS_PER_FRAME),
+        )
+    }
+
+    /// Starts recording when asked to, after the CPU has been configured.
+    fn start_recording<D: Display>(&self, rom_data: &[u8], cpu: &CPU<D>) -> Option<Movie> {
+        self.record_path.as_ref()?;
+        let seed = self.seed.expect("recording always has a seed");
+        Some(Movie::new(rom_data, self.get_load_address(), cpu, seed))
+    }
+
+    fn save_screenshot(&self, framebuffer: &Framebuffer) {
+        let Some(path) = &self.screenshot_path else {
+            return;
+        };
+        match fs::write(path, framebuffer.to_ppm(&self.palette.unwrap_or_default())) {
+            Ok(()) => println!("Saved the screen to {}", path.display()),
+            Err(error) => eprintln!("Can't write {}: {error}", path.display()),
+        }
+    }
+
+    /// Saves the recording and checks the replay once the session is over.
+    fn finish_movies(
+        &self,
+        recording: Option<Movie>,
+        framebuffer: &Framebuffer,
+        has_replay_ended: bool,
+    ) {
+        if let (Some(mut movie), Some(path)) = (recording, &self.record_path) {
+            movie.finish(framebuffer);
+            match movie.save(path) {
+                Ok(()) => println!(
+                    "Recorded {} frames to {}",
+                    movie.frames.len(),
+                    path.display()
+                ),
+                Err(error) => eprintln!("{error}"),
+            }
+        }
+        if let Some(movie) = &self.replay {
+            if !has_replay_ended {
+                println!("Replay stopped before the end of the movie");
+            } else if let Err(error) = movie.verify(framebuffer) {
+                exit_with_error(&error);
+            } else {
+                println!("Replay matches the recording");
+            }
+        }
+    }
+}
 
 fn main() {
-    let args = env::args();
-    let mut rom_name = String::new();
-    for (argument_number, argument) in args.enumerate() {
-        println!(" {} {} ", argument_number, argument);
-        if argument_number == 1 {
-            rom_name = argument;
-        }
-    }
-    if !rom_name.is_empty() {
-        let rom_data = fs::read(format!("roms/{}", rom_name)).unwrap();
-        //for (address, byte) in rom_data.iter().enumerate() {
-        //println!("{address:#x} {byte:#x}");
-        //}
-        let mut cpu = CPU::new(20);
-        cpu.load_rom(&rom_data);
-        cpu.run();
+    let args: Vec<String> = env::args().skip(1).collect();
+    let command = cli::parse_command(&args).unwrap_or_else(|error| exit_with_error(&error));
+    match command {
+        Command::Run {
+            rom_name,
+            mut settings,
+        } => {
+            let rom_data = settings.prepare(&rom_name);
+            if let Some(frames) = settings.frames {
+                run_headless(&rom_data, &mut settings, frames);
+            } else {
+                let rom_path = (rom_name != STDIN_ROM_NAME).then(|| rom_path(&rom_name));
+                run_window(rom_path.flatten().as_deref(), &rom_data, &mut settings);
+            }
+        }
+        Command::Test {
+            rom_name,
+            mut settings,
+            expected_path,
+        } => {
+            let rom_data = settings.prepare(&rom_name);
+            run_test(&rom_data, &settings, expected_path.as_deref());
+        }
+        Command::Bench {
+            rom_name,
+            mut settings,
+        } => {
+            let rom_data = settings.prepare(&rom_name);
+            run_bench(&rom_data, &settings);
+        }
+        Command::Disassemble {
+            rom_name,
+            load_address,
+        } => print!(
+            "{}",
+            disassembler::disassemble(&read_rom(&rom_name), load_address)
+        ),
+        Command::Assemble {
+            source_path,
+            output_path,
+        } => run_assembler(&source_path, &output_path),
+        Command::Help => println!("{HELP}"),
+    }
+}
+
+#[cfg(feature = "sdl")]
+fn run_window(rom_path: Option<&Path>, rom_data: &[u8], settings: &mut Settings) {
+    let scale = settings.scale.unwrap_or(DEFAULT_SCALE);
+    let mut cpu = CPU::with_display(chip_8::display::DisplayChip8::new(scale));
+    settings.configure(&mut cpu);
+    cpu.display_mut()
+        .set_palette(settings.palette.unwrap_or_default());
+    if settings.is_fullscreen {
+        if let Err(error) = cpu.display_mut().set_fullscreen(true) {
+            eprintln!("Can't go fullscreen: {error}");
+        }
+    }
+    settings.load_rom(&mut cpu, rom_data);
+    let mut runner = chip_8::runner::Runner::new();
+    if let Some(rom_path) = rom_path {
+        runner.set_state_path(rom_path);
+    }
+    let has_movie = settings.record_path.is_some() || settings.replay.is_some();
+    let rewind_seconds = settings.rewind_seconds.unwrap_or(DEFAULT_REWIND_SECONDS);
+    if rewind_seconds > 0 && !has_movie {
+        runner.set_rewind(Rewind::new(rewind_seconds));
     }
+    if let Some(debugger) = settings.debugger.take() {
+        runner.set_debugger(debugger);
+    }
+    runner.set_scheduler(settings.scheduler());
+    runner.set_error_policy(settings.error_policy);
+    if let Some(volume) = settings.volume {
+        runner.set_volume(volume);
+    }
+    if let Err(error) = runner.set_keymap(&settings.keymap) {
+        exit_with_error(&error);
+    }
+    if let Some(movie) = &settings.replay {
+        runner.set_input(Box::new(movie.player()));
+    }
+    if let Some(movie) = settings.start_recording(rom_data, &cpu) {
+        runner.set_recording(movie);
+    }
+    runner.run(&mut cpu);
+    settings.save_screenshot(cpu.framebuffer());
+    settings.finish_movies(
+        runner.take_recording(),
+        cpu.framebuffer(),
+        runner.has_input_ended(),
+    );
+}
+
+#[cfg(not(feature = "sdl"))]
+fn run_window(_rom_path: Option<&Path>, _rom_data: &[u8], _settings: &mut Settings) {
+    eprintln!("Built without SDL support, only --headless is available");
+}
+
+/// Runs the ROM for a number of 60 Hz frames without opening a window and
+/// prints the resulting screen.
+fn run_headless(rom_data: &[u8], settings: &mut Settings, frames: u32) {
+    let mut cpu = CPU::new();
+    settings.configure(&mut cpu);
+    settings.load_rom(&mut cpu, rom_data);
+    let mut recording = settings.start_recording(rom_data, &cpu);
+    let mut input = settings.replay.as_ref().map(Movie::player);
+    let mut has_replay_ended = false;
+    let mut scheduler = settings.scheduler();
+    let mut debugger = settings.debugger.take();
+    let debugger_commands = debugger.is_some().then(debugger::spawn_stdin_reader);
+    if debugger.as_ref().is_some_and(Debugger::is_paused) {
+        println!("Paused, type help for a list of commands");
+        println!("{}", debugger::format_state(&cpu));
+    }
+    let mut frame = 0;
+    while (frame < frames || input.is_some()) && !cpu.is_halted() {
+        if let (Some(debugger), Some(commands)) = (debugger.as_mut(), debugger_commands.as_ref()) {
+            while debugger.is_paused() && !debugger.has_quit() {
+                let Ok(command) = commands.recv() else {
+                    return;
+                };
+                let output = debugger.execute(&command, &mut cpu);
+                if !output.is_empty() {
+                    println!("{output}");
+                }
+            }
+            if debugger.has_quit() {
+                break;
+            }
+        }
+        let instructions = match input.as_mut().map(Input::next_frame) {
+            Some(Some(movie_frame)) => {
+                cpu.set_keys(movie_frame.keys);
+                (0..movie_frame.instructions)
+                    .find(|_| !step(debugger.as_mut(), &mut cpu, settings.error_policy))
+                    .unwrap_or(movie_frame.instructions)
+            }
+            Some(None) => {
+                has_replay_ended = true;
+                break;
+            }
+            None => scheduler.run_frame(&mut cpu, |cpu| {
+                step(debugger.as_mut(), cpu, settings.error_policy)
+            }),
+        };
+        if !debugger.as_ref().is_some_and(Debugger::is_paused) {
+            if let Some(movie) = recording.as_mut() {
+                movie.record_frame(Frame {
+                    keys: *cpu.keys(),
+                    instructions,
+                });
+            }
+            cpu.tick_timers();
+            frame += 1;
+        }
+    }
+    print!("{}", cpu.framebuffer());
+    settings.save_screenshot(cpu.framebuffer());
+    settings.finish_movies(recording, cpu.framebuffer(), has_replay_ended);
+}
+
+/// Runs the ROM without a window, stopping at the first error, and compares
+/// the final screen with the one in `expected_path`. Exits with an error when
+/// either check fails.
+fn run_test(rom_data: &[u8], settings: &Settings, expected_path: Option<&Path>) {
+    let mut cpu = CPU::new();
+    settings.configure(&mut cpu);
+    settings.load_rom(&mut cpu, rom_data);
+    let mut scheduler = settings.scheduler();
+    let mut input = settings.replay.as_ref().map(Movie::player);
+    let frames = settings.frames.unwrap_or(DEFAULT_TEST_FRAMES);
+    let mut error = None;
+    let mut frame = 0;
+    while frame < frames && !cpu.is_halted() {
+        match input.as_mut().map(Input::next_frame) {
+            Some(Some(movie_frame)) => {
+                cpu.set_keys(movie_frame.keys);
+                for _ in 0..movie_frame.instructions {
+                    if !test_step(&mut cpu, &mut error) {
+                        break;
+                    }
+                }
+            }
+            Some(None) => break,
+            None => {
+                scheduler.run_frame(&mut cpu, |cpu| test_step(cpu, &mut error));
+            }
+        }
+        if let Some(error) = error {
+            print!("{}", cpu.framebuffer());
+            exit_with_error(&format!(
+                "FAIL: {error} in frame {frame}\n{}",
+                debugger::format_state(&cpu)
+            ));
+        }
+        cpu.tick_timers();
+        frame += 1;
+    }
+    let screen = cpu.framebuffer().to_string();
+    print!("{screen}");
+    settings.save_screenshot(cpu.framebuffer());
+    if let Some(path) = expected_path {
+        let expected = fs::read_to_string(path).unwrap_or_else(|error| {
+            exit_with_error(&format!("Can't read {}: {error}", path.display()))
+        });
+        if expected.trim_end() != screen.trim_end() {
+            exit_with_error(&format!("FAIL: the screen differs from {}", path.display()));
+        }
+    }
+    println!("PASS after {frame} frames");
+}
+
+/// Executes one instruction, keeping its error. Returns `false` on errors.
+fn test_step<D: Display>(cpu: &mut CPU<D>, error: &mut Option<Chip8Error>) -> bool {
+    match cpu.step() {
+        Ok(()) => true,
+        Err(step_error) => {
+            *error = Some(step_error);
+            false
+        }
+    }
+}
+
+/// Executes one instruction through [`debugger::step`], printing what it
+/// reports. Returns `false` when execution should stop.
+fn step<D: Display>(
+    debugger: Option<&mut Debugger>,
+    cpu: &mut CPU<D>,
+    policy: ErrorPolicy,
+) -> bool {
+    let (has_run, report) = debugger::step(debugger, cpu, policy);
+    match report {
+        Some(Report::Stopped(report)) => println!("{report}"),
+        Some(Report::Halted(error)) => eprintln!("{error}\n{}", debugger::format_state(cpu)),
+        Some(Report::Ignored(error)) => eprintln!("{error}"),
+        None => (),
+    }
+    has_run
+}
+
+/// Runs the ROM without a window or frame pacing and reports how fast it went
+/// compared to real time.
+fn run_bench(rom_data: &[u8], settings: &Settings) {
+    let mut cpu = CPU::new();
+    settings.configure(&mut cpu);
+    settings.load_rom(&mut cpu, rom_data);
+    let mut scheduler = settings.scheduler();
+    let frames = settings.frames.unwrap_or(DEFAULT_TEST_FRAMES);
+    let mut instructions = 0u64;
+    let start = Instant::now();
+    for _ in 0..frames {
+        instructions +=
+            scheduler.run_frame(&mut cpu, |cpu| step(None, cpu, settings.error_policy)) as u64;
+        cpu.tick_timers();
+    }
+    let seconds = start.elapsed().as_secs_f64().max(f64::EPSILON);
+    println!(
+        "{frames} frames, {instructions} instructions in {seconds:.3} s: \
+         {:.0} instructions/s, {:.1}x real time",
+        instructions as f64 / seconds,
+        frames as f64 / FRAMES_PER_SECOND as f64 / seconds
+    );
+}
+
+/// Assembles the source file into a ROM, reporting errors with their
+/// position in the source.
+fn run_assembler(source_path: &Path, output_path: &Path) {
+    let source = fs::read_to_string(source_path).unwrap_or_else(|error| {
+        exit_with_error(&format!("Can't read {}: {error}", source_path.display()))
+    });
+    let rom = assembler::assemble(&source)
+        .unwrap_or_else(|error| exit_with_error(&format!("{}:{error}", source_path.display())));
+    if let Err(error) = fs::write(output_path, &rom) {
+        exit_with_error(&format!("Can't write {}: {error}", output_path.display()));
+    }
+    println!("Wrote {} bytes to {}", rom.len(), output_path.display());
+}
+
+/// Finds a ROM given as a path, falling back to the `roms` directory.
+fn rom_path(rom_name: &str) -> Option<PathBuf> {
+    let path = PathBuf::from(rom_name);
+    if path.exists() {
+        return Some(path);
+    }
+    let path = Path::new("roms").join(rom_name);
+    path.exists().then_some(path)
+}
+
+/// Reads a ROM from a file, or from stdin for [`STDIN_ROM_NAME`].
+fn read_rom(rom_name: &str) -> Vec<u8> {
+    let rom_data = if rom_name == STDIN_ROM_NAME {
+        let mut rom_data = Vec::new();
+        if let Err(error) = io::stdin().read_to_end(&mut rom_data) {
+            exit_with_error(&format!("Can't read the ROM from stdin: {error}"));
+        }
+        rom_data
+    } else {
+        let Some(path) = rom_path(rom_name) else {
+            exit_with_error(&format!("ROM {rom_name} not found"));
+        };
+        fs::read(&path).unwrap_or_else(|error| {
+            exit_with_error(&format!("Can't read ROM {}: {error}", path.display()))
+        })
+    };
+    if rom_data.is_empty() {
+        eprintln!("Warning: the ROM is empty");
+    }
+    rom_data
+}
+
+fn exit_with_error(message: &str) -> ! {
+    eprintln!("{message}");
+    process::exit(1);
 }
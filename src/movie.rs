@@ -0,0 +1,234 @@
+use crate::binary::{self, Reader};
+use crate::cpu::CPU;
+use crate::display::{Display, Framebuffer};
+use crate::input::{Frame, Input};
+use crate::platform::Platform;
+use crate::quirks::Quirks;
+use crate::sha1::{self, Digest};
+use std::fs;
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"C8MV";
+/// Bumped whenever the layout below or the way recorded input is handled
+/// changes; older files are rejected.
+pub const MOVIE_VERSION: u16 = 3;
+
+/// A recorded session: the input of every frame plus everything else that
+/// decides how the ROM runs, so replaying it reproduces the session exactly.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_hash: Digest,
+    pub load_address: u16,
+    pub platform: Platform,
+    pub quirks: Quirks,
+    pub stack_depth: usize,
+    pub stack_address: Option<u16>,
+    pub seed: u64,
+    pub frames: Vec<Frame>,
+    /// Hash of the screen once the last frame ran, checked after replaying.
+    pub framebuffer_hash: Digest,
+}
+
+impl Movie {
+    /// Starts an empty recording of `cpu`, which has to be configured already
+    /// and use `seed`.
+    pub fn new<D: Display>(rom: &[u8], load_address: u16, cpu: &CPU<D>, seed: u64) -> Self {
+        Self {
+            rom_hash: sha1::sha1(rom),
+            load_address,
+            platform: cpu.platform(),
+            quirks: *cpu.quirks(),
+            stack_depth: cpu.get_stack_depth(),
+            stack_address: cpu.get_stack_address(),
+            seed,
+            frames: Vec::new(),
+            framebuffer_hash: [0; 20],
+        }
+    }
+
+    pub fn record_frame(&mut self, frame: Frame) {
+        self.frames.push(frame);
+    }
+
+    /// Ends the recording with the screen the replay will have to match.
+    pub fn finish(&mut self, framebuffer: &Framebuffer) {
+        self.framebuffer_hash = hash_framebuffer(framebuffer);
+    }
+
+    /// Sets up a CPU the way the recording was made. The ROM has to be loaded
+    /// at `load_address` too.
+    pub fn configure<D: Display>(&self, cpu: &mut CPU<D>) {
+        cpu.set_platform(self.platform);
+        cpu.set_quirks(self.quirks);
+        cpu.set_stack_depth(self.stack_depth);
+        cpu.set_stack_address(self.stack_address);
+        cpu.set_seed(self.seed);
+    }
+
+    pub fn check_rom(&self, rom: &[u8]) -> Result<(), String> {
+        let rom_hash = sha1::sha1(rom);
+        if rom_hash == self.rom_hash {
+            Ok(())
+        } else {
+            Err(format!(
+                "Movie was recorded with ROM {}, not {}",
+                sha1::to_hex(&self.rom_hash),
+                sha1::to_hex(&rom_hash)
+            ))
+        }
+    }
+
+    /// Checks that a replay ended on the same screen as the recording.
+    pub fn verify(&self, framebuffer: &Framebuffer) -> Result<(), String> {
+        let framebuffer_hash = hash_framebuffer(framebuffer);
+        if framebuffer_hash == self.framebuffer_hash {
+            Ok(())
+        } else {
+            Err(format!(
+                "Replay diverged: screen hash {} instead of {}",
+                sha1::to_hex(&framebuffer_hash),
+                sha1::to_hex(&self.framebuffer_hash)
+            ))
+        }
+    }
+
+    pub fn player(&self) -> MoviePlayer {
+        MoviePlayer {
+            frames: self.frames.clone(),
+            position: 0,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(64 + self.frames.len() * 6);
+        bytes.extend(MAGIC);
+        bytes.extend(MOVIE_VERSION.to_le_bytes());
+        bytes.extend(self.rom_hash);
+        bytes.extend(self.load_address.to_le_bytes());
+        bytes.push(self.platform.id());
+        bytes.push(self.quirks.to_bits());
+        bytes.extend((self.stack_depth as u32).to_le_bytes());
+        match self.stack_address {
+            Some(address) => {
+                bytes.push(1);
+                bytes.extend(address.to_le_bytes());
+            }
+            None => bytes.push(0),
+        }
+        bytes.extend(self.seed.to_le_bytes());
+        bytes.extend(self.framebuffer_hash);
+        bytes.extend((self.frames.len() as u32).to_le_bytes());
+        for frame in &self.frames {
+            bytes.extend(binary::keys_to_bits(&frame.keys).to_le_bytes());
+            bytes.extend(frame.instructions.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader::new(bytes, "Movie");
+        if reader.read_bytes(MAGIC.len())? != MAGIC {
+            return Err(String::from("Not a movie file"));
+        }
+        let version = reader.read_u16()?;
+        if version != MOVIE_VERSION {
+            return Err(format!(
+                "Movie version {version} is not supported, expected {MOVIE_VERSION}"
+            ));
+        }
+        let rom_hash = reader.read_array()?;
+        let load_address = reader.read_u16()?;
+        let platform_id = reader.read_u8()?;
+        let platform = Platform::from_id(platform_id)
+            .ok_or_else(|| format!("Unknown platform {platform_id} in movie"))?;
+        let quirks = Quirks::from_bits(reader.read_u8()?);
+        let stack_depth = reader.read_u32()? as usize;
+        let stack_address = match reader.read_u8()? {
+            0 => None,
+            _ => Some(reader.read_u16()?),
+        };
+        let seed = reader.read_u64()?;
+        let framebuffer_hash = reader.read_array()?;
+        let frame_count = reader.read_u32()?;
+        let frames = (0..frame_count)
+            .map(|_| {
+                let keys = reader.read_keys()?;
+                let instructions = reader.read_u32()?;
+                Ok(Frame { keys, instructions })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self {
+            rom_hash,
+            load_address,
+            platform,
+            quirks,
+            stack_depth,
+            stack_address,
+            seed,
+            frames,
+            framebuffer_hash,
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.to_bytes())
+            .map_err(|error| format!("Can't write {}: {error}", path.display()))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let bytes =
+            fs::read(path).map_err(|error| format!("Can't read {}: {error}", path.display()))?;
+        Self::from_bytes(&bytes)
+    }
+}
+
+/// Plays back the frames of a movie as [`Input`].
+pub struct MoviePlayer {
+    frames: Vec<Frame>,
+    position: usize,
+}
+
+impl Input for MoviePlayer {
+    fn next_frame(&mut self) -> Option<Frame> {
+        let frame = self.frames.get(self.position).copied()?;
+        self.position += 1;
+        Some(frame)
+    }
+}
+
+/// Hashes the resolution and every pixel, so screens only match when they
+/// look the same.
+pub fn hash_framebuffer(framebuffer: &Framebuffer) -> Digest {
+    let mut data = Vec::with_capacity(framebuffer.pixels().len() + 4);
+    data.extend((framebuffer.width() as u16).to_le_bytes());
+    data.extend((framebuffer.height() as u16).to_le_bytes());
+    data.extend(framebuffer.pixels());
+    sha1::sha1(&data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::VIP_STACK_ADDRESS;
+
+    #[test]
+    fn loads_what_it_saves() {
+        let mut cpu = CPU::new();
+        cpu.set_platform(Platform::SuperChip);
+        cpu.set_stack_depth(12);
+        cpu.set_stack_address(Some(VIP_STACK_ADDRESS));
+        let mut movie = Movie::new(&[0x12, 0x00], 0x600, &cpu, 42);
+        let mut keys = [false; 16];
+        keys[0xA] = true;
+        movie.record_frame(Frame {
+            keys,
+            instructions: 11,
+        });
+        movie.finish(cpu.framebuffer());
+        let path = std::env::temp_dir().join(format!("chip-8-movie-{}.c8m", std::process::id()));
+        movie.save(&path).unwrap();
+        let loaded = Movie::load(&path);
+        let _ = fs::remove_file(&path);
+        assert_eq!(loaded, Ok(movie));
+    }
+}
@@ -0,0 +1,87 @@
+use crate::quirks::Quirks;
+use std::str::FromStr;
+
+/// Names accepted by [`Platform::from_str`].
+pub const PLATFORM_NAMES: [&str; 3] = ["chip8", "schip", "xochip"];
+
+/// The instruction set the CPU understands.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Platform {
+    #[default]
+    Chip8,
+    /// SUPER-CHIP 1.1: hires mode, scrolling, 16x16 sprites, the big font and
+    /// the RPL user flags.
+    SuperChip,
+    /// XO-CHIP: everything SUPER-CHIP has plus 64 KiB of memory, bitplanes and
+    /// audio patterns.
+    XoChip,
+}
+
+impl Platform {
+    /// The quirks ROMs written for this platform usually expect.
+    pub fn default_quirks(&self) -> Quirks {
+        match self {
+            Platform::Chip8 => Quirks::default(),
+            Platform::SuperChip => Quirks::superchip(),
+            Platform::XoChip => Quirks::xochip(),
+        }
+    }
+
+    /// Stable number identifying the platform in save states and movies.
+    pub fn id(&self) -> u8 {
+        match self {
+            Platform::Chip8 => 0,
+            Platform::SuperChip => 1,
+            Platform::XoChip => 2,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Platform::Chip8),
+            1 => Some(Platform::SuperChip),
+            2 => Some(Platform::XoChip),
+            _ => None,
+        }
+    }
+
+    pub fn has_superchip_instructions(&self) -> bool {
+        *self != Platform::Chip8
+    }
+
+    pub fn has_xochip_instructions(&self) -> bool {
+        *self == Platform::XoChip
+    }
+
+    pub fn memory_size(&self) -> usize {
+        match self {
+            Platform::Chip8 | Platform::SuperChip => 0x1000,
+            Platform::XoChip => 0x10000,
+        }
+    }
+
+    /// Number of RPL user flags FX75/FX85 can access.
+    pub fn rpl_flag_count(&self) -> usize {
+        match self {
+            Platform::Chip8 => 0,
+            Platform::SuperChip => 8,
+            Platform::XoChip => 16,
+        }
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "chip8" | "chip-8" => Ok(Platform::Chip8),
+            "schip" | "superchip" => Ok(Platform::SuperChip),
+            "xochip" | "xo-chip" => Ok(Platform::XoChip),
+            _ => Err(format!(
+                "Unknown platform {name}, expected one of: {}",
+                PLATFORM_NAMES.join(", ")
+            )),
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use std::str::FromStr;
+
+/// Names accepted by [`Quirks::from_str`].
+pub const PRESET_NAMES: [&str; 5] = ["default", "vip", "chip48", "schip", "xochip"];
+
+/// Behaviour of the opcodes that CHIP-8 interpreters disagree on.
+///
+/// Each preset mirrors one well known interpreter; `Default` keeps the
+/// behaviour this interpreter always had.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8XY6/8XYE shift VY into VX instead of shifting VX in place.
+    pub shift_uses_vy: bool,
+    /// BNNN is read as BXNN and jumps to XNN + VX instead of NNN + V0.
+    pub jump_uses_vx: bool,
+    /// FX55/FX65 leave I pointing just past the last byte they accessed.
+    pub load_store_increments_index: bool,
+    /// 8XY1/8XY2/8XY3 reset VF to 0.
+    pub logic_resets_flag: bool,
+    /// Sprites are cut at the screen edges instead of wrapping around.
+    pub clip_sprites: bool,
+    /// DXYN waits for the next 60 Hz frame, so at most one sprite is drawn
+    /// per frame.
+    pub display_wait: bool,
+    /// FX0A finishes when the pressed key is released rather than as soon as
+    /// it goes down.
+    pub key_wait_release: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self {
+            shift_uses_vy: true,
+            jump_uses_vx: false,
+            load_store_increments_index: false,
+            logic_resets_flag: false,
+            clip_sprites: true,
+            display_wait: false,
+            key_wait_release: false,
+        }
+    }
+}
+
+impl Quirks {
+    /// Packs the quirks into one bit each, in field order.
+    pub fn to_bits(&self) -> u8 {
+        [
+            self.shift_uses_vy,
+            self.jump_uses_vx,
+            self.load_store_increments_index,
+            self.logic_resets_flag,
+            self.clip_sprites,
+            self.display_wait,
+            self.key_wait_release,
+        ]
+        .iter()
+        .enumerate()
+        .fold(0, |bits, (bit, is_set)| bits | (*is_set as u8) << bit)
+    }
+
+    pub fn from_bits(bits: u8) -> Self {
+        let is_set = |bit: u8| bits & (1 << bit) != 0;
+        Self {
+            shift_uses_vy: is_set(0),
+            jump_uses_vx: is_set(1),
+            load_store_increments_index: is_set(2),
+            logic_resets_flag: is_set(3),
+            clip_sprites: is_set(4),
+            display_wait: is_set(5),
+            key_wait_release: is_set(6),
+        }
+    }
+
+    /// The original COSMAC VIP interpreter.
+    pub fn cosmac_vip() -> Self {
+        Self {
+            shift_uses_vy: true,
+            jump_uses_vx: false,
+            load_store_increments_index: true,
+            logic_resets_flag: true,
+            clip_sprites: true,
+            display_wait: true,
+            key_wait_release: true,
+        }
+    }
+
+    /// CHIP-48 on the HP-48 calculators.
+    pub fn chip48() -> Self {
+        Self {
+            shift_uses_vy: false,
+            jump_uses_vx: true,
+            load_store_increments_index: false,
+            logic_resets_flag: false,
+            clip_sprites: true,
+            display_wait: false,
+            key_wait_release: true,
+        }
+    }
+
+    /// SUPER-CHIP 1.1.
+    pub fn superchip() -> Self {
+        Self {
+            shift_uses_vy: false,
+            jump_uses_vx: true,
+            load_store_increments_index: false,
+            logic_resets_flag: false,
+            clip_sprites: true,
+            display_wait: false,
+            key_wait_release: true,
+        }
+    }
+
+    /// XO-CHIP as implemented by Octo.
+    pub fn xochip() -> Self {
+        Self {
+            shift_uses_vy: true,
+            jump_uses_vx: false,
+            load_store_increments_index: true,
+            logic_resets_flag: false,
+            clip_sprites: false,
+            display_wait: false,
+            key_wait_release: true,
+        }
+    }
+}
+
+impl FromStr for Quirks {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "default" => Ok(Self::default()),
+            "vip" | "cosmac" => Ok(Self::cosmac_vip()),
+            "chip48" => Ok(Self::chip48()),
+            "schip" | "superchip" => Ok(Self::superchip()),
+            "xochip" => Ok(Self::xochip()),
+            _ => Err(format!(
+                "Unknown quirks preset {name}, expected one of: {}",
+                PRESET_NAMES.join(", ")
+            )),
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use crate::cpu::Snapshot;
+use std::collections::VecDeque;
+
+pub const DEFAULT_REWIND_SECONDS: usize = 10;
+const FRAMES_PER_SECOND: usize = 60;
+
+/// How to get from one state to the one captured before it: the serialized
+/// states XORed together, with the runs of zeros that leaves compressed.
+struct Delta {
+    /// Length of the older serialized state.
+    length: usize,
+    data: Vec<u8>,
+}
+
+/// Rolling history of machine states, one per frame.
+///
+/// Only the newest state is kept whole. Every older one is stored as the
+/// difference to its successor, which is tiny since a frame rarely touches
+/// more than a few bytes of memory and screen.
+pub struct Rewind {
+    latest: Option<Vec<u8>>,
+    deltas: VecDeque<Delta>,
+    capacity: usize,
+}
+
+impl Default for Rewind {
+    fn default() -> Self {
+        Self::new(DEFAULT_REWIND_SECONDS)
+    }
+}
+
+impl Rewind {
+    /// Keeps enough frames to step back `seconds` seconds at 60 Hz.
+    pub fn new(seconds: usize) -> Self {
+        Self {
+            latest: None,
+            deltas: VecDeque::new(),
+            capacity: seconds * FRAMES_PER_SECOND,
+        }
+    }
+
+    /// Records the state of a new frame, dropping the oldest one when full.
+    pub fn push(&mut self, snapshot: &Snapshot) {
+        let state = snapshot.to_bytes();
+        if let Some(latest) = self.latest.take() {
+            self.deltas.push_back(Delta {
+                length: latest.len(),
+                data: compress(&xor(&state, &latest)),
+            });
+            if self.deltas.len() > self.capacity {
+                self.deltas.pop_front();
+            }
+        }
+        self.latest = Some(state);
+    }
+
+    /// Steps one frame back and returns that state, or `None` once the
+    /// history is used up.
+    pub fn pop(&mut self) -> Option<Snapshot> {
+        let delta = self.deltas.pop_back()?;
+        let latest = self.latest.as_mut()?;
+        let mut state = xor(latest, &decompress(&delta.data));
+        state.truncate(delta.length);
+        *latest = state;
+        Snapshot::from_bytes(latest).ok()
+    }
+
+    /// Number of frames that can be stepped back.
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+    }
+
+    /// Bytes used by the stored states.
+    pub fn size(&self) -> usize {
+        let latest_size = self.latest.as_ref().map_or(0, Vec::len);
+        latest_size
+            + self
+                .deltas
+                .iter()
+                .map(|delta| delta.data.len())
+                .sum::<usize>()
+    }
+}
+
+/// XORs two buffers, treating the shorter one as padded with zeros.
+fn xor(first: &[u8], second: &[u8]) -> Vec<u8> {
+    let length = first.len().max(second.len());
+    (0..length)
+        .map(|position| {
+            first.get(position).copied().unwrap_or(0) ^ second.get(position).copied().unwrap_or(0)
+        })
+        .collect()
+}
+
+/// Run-length encodes zeros: pairs of a zero run length and a literal run
+/// length, each followed by the literal bytes.
+fn compress(bytes: &[u8]) -> Vec<u8> {
+    let mut compressed = Vec::new();
+    let mut position = 0;
+    while position < bytes.len() {
+        let zeros = bytes[position..]
+            .iter()
+            .take_while(|byte| **byte == 0)
+            .count();
+        position += zeros;
+        let literals = bytes[position..]
+            .iter()
+            .take_while(|byte| **byte != 0)
+            .count();
+        write_length(&mut compressed, zeros);
+        write_length(&mut compressed, literals);
+        compressed.extend(&bytes[position..position + literals]);
+        position += literals;
+    }
+    compressed
+}
+
+fn decompress(compressed: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut position = 0;
+    while position < compressed.len() {
+        let zeros = read_length(compressed, &mut position);
+        let literals = read_length(compressed, &mut position);
+        bytes.resize(bytes.len() + zeros, 0);
+        let end = (position + literals).min(compressed.len());
+        bytes.extend(&compressed[position..end]);
+        position = end;
+    }
+    bytes
+}
+
+/// Writes a length 7 bits at a time, the high bit marking that more follow.
+fn write_length(output: &mut Vec<u8>, mut length: usize) {
+    while length >= 0x80 {
+        output.push(length as u8 | 0x80);
+        length >>= 7;
+    }
+    output.push(length as u8);
+}
+
+fn read_length(input: &[u8], position: &mut usize) -> usize {
+    let mut length = 0;
+    let mut shift = 0;
+    while let Some(byte) = input.get(*position) {
+        *position += 1;
+        length |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+    length
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compresses_an_empty_delta() {
+        assert!(compress(&[]).is_empty());
+        assert!(decompress(&[]).is_empty());
+    }
+
+    #[test]
+    fn compresses_zero_runs_longer_than_a_length_byte() {
+        let mut bytes = vec![0; 10_000];
+        bytes.push(0x42);
+        bytes.extend([0; 300]);
+        let compressed = compress(&bytes);
+        assert!(compressed.len() < 10);
+        assert_eq!(decompress(&compressed), bytes);
+    }
+
+    #[test]
+    fn compresses_a_delta_without_zeros() {
+        let bytes: Vec<u8> = (1..=200).collect();
+        assert_eq!(decompress(&compress(&bytes)), bytes);
+    }
+
+    #[test]
+    fn undoes_a_delta_between_states_of_different_lengths() {
+        let older = [1, 2, 3, 4, 5];
+        let newer = [1, 2, 7];
+        let delta = compress(&xor(&newer, &older));
+        let mut restored = xor(&newer, &decompress(&delta));
+        restored.truncate(older.len());
+        assert_eq!(restored, older);
+    }
+}
@@ -0,0 +1,527 @@
+use crate::clock::{FrameClock, FRAMES_PER_SECOND};
+use crate::cpu::{Snapshot, CPU};
+use crate::debugger::{self, Debugger, Report};
+use crate::display::{Display, DisplayChip8};
+use crate::error::ErrorPolicy;
+use crate::input::{Frame, Input};
+use crate::keymap::Keymap;
+use crate::movie::Movie;
+use crate::rewind::Rewind;
+use crate::scheduler::{Scheduler, Timing};
+use sdl2::audio::AudioCallback;
+use sdl2::audio::AudioSpecDesired;
+use sdl2::controller::{Axis, Button, GameController};
+use sdl2::event::Event;
+use sdl2::keyboard::{KeyboardState, Mod, Scancode};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// F1 to F8 load the matching save state slot, with Shift they save it.
+const STATE_SLOT_SCANCODES: [Scancode; 8] = [
+    Scancode::F1,
+    Scancode::F2,
+    Scancode::F3,
+    Scancode::F4,
+    Scancode::F5,
+    Scancode::F6,
+    Scancode::F7,
+    Scancode::F8,
+];
+
+/// Toggles pausing; while paused the frame advance key runs a single frame.
+const PAUSE_SCANCODE: Scancode = Scancode::P;
+const FRAME_ADVANCE_SCANCODE: Scancode = Scancode::N;
+/// Held to run several frames for every real one.
+const FAST_FORWARD_SCANCODE: Scancode = Scancode::Tab;
+const FAST_FORWARD_FRAMES: u32 = 4;
+
+/// A game controller input that can press a CHIP-8 key.
+#[derive(Clone, Copy)]
+enum ControllerInput {
+    Button(Button),
+    /// Pushing the axis past the deadzone in one direction.
+    Axis {
+        axis: Axis,
+        is_positive: bool,
+    },
+}
+
+impl ControllerInput {
+    fn from_name(name: &str) -> Option<Self> {
+        let axis_direction = name
+            .strip_suffix('+')
+            .map(|axis| (axis, true))
+            .or_else(|| name.strip_suffix('-').map(|axis| (axis, false)));
+        match axis_direction {
+            Some((axis, is_positive)) => Some(Self::Axis {
+                axis: Axis::from_string(axis)?,
+                is_positive,
+            }),
+            None => Button::from_string(name).map(Self::Button),
+        }
+    }
+
+    fn is_pressed(&self, controller: &GameController, threshold: i32) -> bool {
+        match *self {
+            Self::Button(button) => controller.button(button),
+            Self::Axis { axis, is_positive } => {
+                let value = controller.axis(axis) as i32;
+                if is_positive {
+                    value > threshold
+                } else {
+                    value < -threshold
+                }
+            }
+        }
+    }
+}
+
+/// A keymap resolved to the SDL inputs it names.
+#[derive(Clone)]
+struct Bindings {
+    keys: Vec<(Scancode, u8)>,
+    controller_keys: Vec<(ControllerInput, u8)>,
+    /// How far an axis has to be pushed to count, in SDL axis units.
+    axis_threshold: i32,
+}
+
+impl Bindings {
+    fn new(keymap: &Keymap) -> Result<Self, String> {
+        let keys = keymap
+            .bindings()
+            .map(|(name, key)| {
+                Scancode::from_name(name)
+                    .map(|scancode| (scancode, key))
+                    .ok_or_else(|| format!("Unknown key name {name:?} for CHIP-8 key {key:X}"))
+            })
+            .collect::<Result<_, String>>()?;
+        let controller_keys = keymap
+            .controller_bindings()
+            .map(|(name, key)| {
+                ControllerInput::from_name(name)
+                    .map(|input| (input, key))
+                    .ok_or_else(|| {
+                        format!("Unknown controller input {name:?} for CHIP-8 key {key:X}")
+                    })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self {
+            keys,
+            controller_keys,
+            axis_threshold: (keymap.get_deadzone() * i16::MAX as f32) as i32,
+        })
+    }
+
+    /// The CHIP-8 keys held on the keyboard or any of the controllers.
+    fn read_keys<'a>(
+        &self,
+        keyboard: &KeyboardState,
+        controllers: impl Iterator<Item = &'a GameController>,
+    ) -> [bool; 16] {
+        let mut keys = [false; 16];
+        for (scancode, key) in &self.keys {
+            keys[*key as usize] |= keyboard.is_scancode_pressed(*scancode);
+        }
+        for controller in controllers {
+            for (input, key) in &self.controller_keys {
+                keys[*key as usize] |= input.is_pressed(controller, self.axis_threshold);
+            }
+        }
+        keys
+    }
+}
+
+struct SquareWave {
+    phase_inc: f32,
+    phase: f32,
+    volume: f32,
+    sample_rate: f32,
+    /// XO-CHIP audio pattern played instead of the square wave when set.
+    pattern: Option<[u8; 16]>,
+    pattern_phase_inc: f32,
+}
+
+impl SquareWave {
+    fn set_pattern(&mut self, pattern: Option<[u8; 16]>, playback_rate: f32) {
+        self.pattern = pattern;
+        self.pattern_phase_inc = playback_rate / 128.0 / self.sample_rate;
+    }
+}
+
+impl AudioCallback for SquareWave {
+    type Channel = f32;
+    fn callback(&mut self, out: &mut [f32]) {
+        if let Some(pattern) = self.pattern {
+            //Play the pattern one bit at a time, 128 bits per period
+            for x in out.iter_mut() {
+                let bit = (self.phase * 128.0) as usize % 128;
+                let is_set = pattern[bit / 8] & (0b10000000 >> (bit % 8)) != 0;
+                *x = if is_set { self.volume } else { -self.volume };
+                self.phase = (self.phase + self.pattern_phase_inc) % 1.0;
+            }
+            return;
+        }
+        //Generate a square wave
+        for x in out.iter_mut() {
+            *x = if self.phase <= 0.5 {
+                self.volume
+            } else {
+                -self.volume
+            };
+            self.phase = (self.phase + self.phase_inc) % 1.0;
+        }
+    }
+}
+
+/// Amplitude of the buzzer, from 0 to 1.
+pub const DEFAULT_VOLUME: f32 = 0.1;
+
+/// Drives a CPU in its SDL window: timing, audio, keyboard or recorded input
+/// and, when enabled, the debugger.
+#[derive(Default)]
+pub struct Runner {
+    debugger: Option<Debugger>,
+    state_path: Option<PathBuf>,
+    rewind: Option<Rewind>,
+    input: Option<Box<dyn Input>>,
+    recording: Option<Movie>,
+    has_input_ended: bool,
+    bindings: Option<Bindings>,
+    scheduler: Scheduler,
+    error_policy: ErrorPolicy,
+    volume: Option<f32>,
+}
+
+impl Runner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs under the debugger, taking commands from stdin. Execution starts
+    /// paused.
+    pub fn set_debugger(&mut self, debugger: Debugger) {
+        self.debugger = Some(debugger);
+    }
+
+    /// Enables the save state hotkeys, keeping slot files next to the ROM.
+    pub fn set_state_path(&mut self, rom_path: &Path) {
+        self.state_path = Some(rom_path.to_path_buf());
+    }
+
+    /// Records every frame so that holding Backspace steps back in time.
+    pub fn set_rewind(&mut self, rewind: Rewind) {
+        self.rewind = Some(rewind);
+    }
+
+    /// What to do when the program runs into an error. [`ErrorPolicy::Debug`]
+    /// needs a debugger.
+    pub fn set_error_policy(&mut self, error_policy: ErrorPolicy) {
+        self.error_policy = error_policy;
+    }
+
+    /// Sets the amplitude of the buzzer, from 0 for silence to 1.
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = Some(volume.clamp(0.0, 1.0));
+    }
+
+    /// Decides how many instructions run in each 60 Hz frame.
+    pub fn set_scheduler(&mut self, scheduler: Scheduler) {
+        self.scheduler = scheduler;
+    }
+
+    /// Adds `change` instructions per frame, keeping at least one. The VIP
+    /// timing has a fixed speed.
+    fn change_speed(&mut self, change: i32) {
+        let instructions_per_frame =
+            (self.scheduler.get_instructions_per_frame() as i32 + change).max(1) as u32;
+        self.scheduler
+            .set_instructions_per_frame(instructions_per_frame);
+    }
+
+    fn get_title(&self, is_paused: bool, is_fast_forwarding: bool) -> String {
+        let mut title = match self.scheduler.get_timing() {
+            Timing::InstructionsPerFrame => {
+                let instructions_per_frame = self.scheduler.get_instructions_per_frame();
+                format!(
+                    "CHIP8 - {} instructions/s ({instructions_per_frame}/frame)",
+                    instructions_per_frame * FRAMES_PER_SECOND
+                )
+            }
+            Timing::VipCycles => String::from("CHIP8 - COSMAC VIP timing"),
+        };
+        if is_paused {
+            title.push_str(" - Paused");
+        } else if is_fast_forwarding {
+            title.push_str(&format!(" - Fast forward x{FAST_FORWARD_FRAMES}"));
+        }
+        title
+    }
+
+    /// Reads the keyboard and game controllers with `keymap` instead of the
+    /// default layout.
+    pub fn set_keymap(&mut self, keymap: &Keymap) -> Result<(), String> {
+        self.bindings = Some(Bindings::new(keymap)?);
+        Ok(())
+    }
+
+    /// Takes keys from `input` instead of the keyboard. The run ends when the
+    /// input does.
+    pub fn set_input(&mut self, input: Box<dyn Input>) {
+        self.input = Some(input);
+    }
+
+    /// Appends every frame to `movie` until [`Runner::take_recording`].
+    pub fn set_recording(&mut self, movie: Movie) {
+        self.recording = Some(movie);
+    }
+
+    /// Whether the last run stopped because the input set with
+    /// [`Runner::set_input`] ran out.
+    pub fn has_input_ended(&self) -> bool {
+        self.has_input_ended
+    }
+
+    pub fn take_recording(&mut self) -> Option<Movie> {
+        self.recording.take()
+    }
+
+    fn get_state_slot_path(&self, slot: usize) -> Option<PathBuf> {
+        let rom_path = self.state_path.as_ref()?;
+        Some(rom_path.with_extension(format!("state{slot}")))
+    }
+
+    fn handle_state_hotkey(&self, cpu: &mut CPU<DisplayChip8>, slot: usize, is_saving: bool) {
+        let Some(path) = self.get_state_slot_path(slot) else {
+            return;
+        };
+        // Movies only hold the keys, a load would make them diverge
+        if !is_saving && (self.input.is_some() || self.recording.is_some()) {
+            eprintln!("Can't load a state while recording or replaying a movie");
+            return;
+        }
+        let result = if is_saving {
+            cpu.snapshot().save(&path).map(|_| "Saved")
+        } else {
+            Snapshot::load(&path)
+                .and_then(|snapshot| cpu.restore(&snapshot))
+                .map(|_| "Loaded")
+        };
+        match result {
+            Ok(action) => println!("{action} state {slot} ({})", path.display()),
+            Err(error) => eprintln!("{error}"),
+        }
+    }
+
+    /// Runs the CPU until the window is closed or the program exits.
+    pub fn run(&mut self, cpu: &mut CPU<DisplayChip8>) {
+        let sdl_context = cpu.display().canvas.window().subsystem().sdl();
+        let audio_subsystem = sdl_context.audio().unwrap();
+        let desired_spec = AudioSpecDesired {
+            freq: Some(44100),
+            channels: Some(1), // Mono
+            samples: None,     // default sample size
+        };
+
+        let volume = self.volume.unwrap_or(DEFAULT_VOLUME);
+        let mut device = audio_subsystem
+            .open_playback(None, &desired_spec, |spec| {
+                // initialize the audio callback
+                SquareWave {
+                    phase_inc: 440.0 / spec.freq as f32,
+                    phase: 0.0,
+                    volume,
+                    sample_rate: spec.freq as f32,
+                    pattern: None,
+                    pattern_phase_inc: 0.0,
+                }
+            })
+            .unwrap();
+
+        // Controllers connected at startup are announced as added too
+        let controller_subsystem = sdl_context
+            .game_controller()
+            .map_err(|error| eprintln!("No game controller support: {error}"))
+            .ok();
+        let mut controllers = HashMap::new();
+        let mut events = sdl_context.event_pump().unwrap();
+        let mut clock = FrameClock::default();
+        let mut is_audio_playing = false;
+        let mut is_paused = false;
+        let mut frames_to_advance = 0;
+        let mut current_title = String::new();
+        let bindings = self
+            .bindings
+            .clone()
+            .unwrap_or_else(|| Bindings::new(&Keymap::default()).unwrap());
+        let debugger_commands = self.debugger.is_some().then(debugger::spawn_stdin_reader);
+        if self.debugger.as_ref().is_some_and(Debugger::is_paused) {
+            println!("Paused, type help for a list of commands");
+            println!("{}", debugger::format_state(cpu));
+        }
+        'gameloop: loop {
+            if let (Some(debugger), Some(commands)) =
+                (self.debugger.as_mut(), debugger_commands.as_ref())
+            {
+                for command in commands.try_iter() {
+                    let output = debugger.execute(&command, cpu);
+                    if !output.is_empty() {
+                        println!("{output}");
+                    }
+                }
+                if debugger.has_quit() {
+                    break 'gameloop;
+                }
+            }
+            let is_debugger_paused = self.debugger.as_ref().is_some_and(Debugger::is_paused);
+            let keyboard_state = events.keyboard_state();
+            let is_rewinding =
+                self.rewind.is_some() && keyboard_state.is_scancode_pressed(Scancode::Backspace);
+            let is_fast_forwarding = keyboard_state.is_scancode_pressed(FAST_FORWARD_SCANCODE);
+            let frame_count = if is_paused {
+                std::mem::take(&mut frames_to_advance)
+            } else if is_fast_forwarding {
+                FAST_FORWARD_FRAMES
+            } else {
+                1
+            };
+            for _ in 0..frame_count {
+                if is_debugger_paused {
+                    break;
+                }
+                match self.rewind.as_mut() {
+                    Some(rewind) if is_rewinding => {
+                        if let Some(snapshot) = rewind.pop() {
+                            if let Err(error) = cpu.restore(&snapshot) {
+                                eprintln!("{error}");
+                            }
+                        }
+                    }
+                    _ => {
+                        // Keys only change between frames so recordings replay exactly
+                        let debugger = &mut self.debugger;
+                        let policy = self.error_policy;
+                        let instructions = match self.input.as_mut() {
+                            Some(input) => {
+                                let Some(frame) = input.next_frame() else {
+                                    self.has_input_ended = true;
+                                    break 'gameloop;
+                                };
+                                cpu.set_keys(frame.keys);
+                                (0..frame.instructions)
+                                    .find(|_| !step(debugger.as_mut(), cpu, policy))
+                                    .unwrap_or(frame.instructions)
+                            }
+                            None => {
+                                let keys = bindings
+                                    .read_keys(&events.keyboard_state(), controllers.values());
+                                cpu.set_keys(keys);
+                                self.scheduler
+                                    .run_frame(cpu, |cpu| step(debugger.as_mut(), cpu, policy))
+                            }
+                        };
+                        if let Some(recording) = self.recording.as_mut() {
+                            recording.record_frame(Frame {
+                                keys: *cpu.keys(),
+                                instructions,
+                            });
+                        }
+                        cpu.tick_timers();
+                        if let Some(rewind) = self.rewind.as_mut() {
+                            rewind.push(&cpu.snapshot());
+                        }
+                    }
+                }
+            }
+            let title = self.get_title(is_paused, is_fast_forwarding);
+            if title != current_title {
+                if let Err(error) = cpu.display_mut().canvas.window_mut().set_title(&title) {
+                    eprintln!("{error}");
+                }
+                current_title = title;
+            }
+            cpu.display_mut().refresh();
+            if is_audio_playing {
+                device.lock().set_pattern(
+                    cpu.audio_pattern().copied(),
+                    cpu.get_pattern_playback_rate(),
+                );
+            }
+            if cpu.is_halted() {
+                break 'gameloop;
+            }
+            if cpu.get_sound_timer() == 0 {
+                device.pause();
+                is_audio_playing = false;
+            } else if !is_audio_playing {
+                is_audio_playing = true;
+                device.lock().set_pattern(
+                    cpu.audio_pattern().copied(),
+                    cpu.get_pattern_playback_rate(),
+                );
+                device.resume();
+            }
+            for event in events.poll_iter() {
+                match event {
+                    Event::Quit { .. } => break 'gameloop,
+                    Event::KeyDown {
+                        scancode: Some(scancode),
+                        keymod,
+                        repeat: false,
+                        ..
+                    } => {
+                        if let Some(slot) = STATE_SLOT_SCANCODES
+                            .iter()
+                            .position(|slot_scancode| *slot_scancode == scancode)
+                        {
+                            let is_saving = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+                            self.handle_state_hotkey(cpu, slot + 1, is_saving);
+                        }
+                        match scancode {
+                            PAUSE_SCANCODE => is_paused = !is_paused,
+                            FRAME_ADVANCE_SCANCODE if is_paused => frames_to_advance += 1,
+                            Scancode::Equals | Scancode::KpPlus => self.change_speed(1),
+                            Scancode::Minus | Scancode::KpMinus => self.change_speed(-1),
+                            _ => (),
+                        }
+                    }
+                    Event::ControllerDeviceAdded { which, .. } => {
+                        let Some(subsystem) = controller_subsystem.as_ref() else {
+                            continue;
+                        };
+                        match subsystem.open(which) {
+                            Ok(controller) => {
+                                println!("Controller connected: {}", controller.name());
+                                controllers.insert(controller.instance_id(), controller);
+                            }
+                            Err(error) => eprintln!("Can't open controller {which}: {error}"),
+                        }
+                    }
+                    Event::ControllerDeviceRemoved { which, .. } => {
+                        if let Some(controller) = controllers.remove(&which) {
+                            println!("Controller disconnected: {}", controller.name());
+                        }
+                    }
+                    _ => (),
+                }
+            }
+            clock.wait();
+        }
+    }
+}
+
+/// Executes one instruction through [`debugger::step`], printing what it
+/// reports. Returns `false` when execution should stop.
+fn step<D: Display>(
+    debugger: Option<&mut Debugger>,
+    cpu: &mut CPU<D>,
+    policy: ErrorPolicy,
+) -> bool {
+    let (has_run, report) = debugger::step(debugger, cpu, policy);
+    match report {
+        Some(Report::Stopped(report)) => println!("{report}"),
+        Some(Report::Halted(error)) => eprintln!("{error}\n{}", debugger::format_state(cpu)),
+        Some(Report::Ignored(error)) => eprintln!("{error}"),
+        None => (),
+    }
+    has_run
+}
@@ -0,0 +1,182 @@
+use crate::clock::{DEFAULT_INSTRUCTIONS_PER_FRAME, FRAMES_PER_SECOND};
+use crate::cpu::CPU;
+use crate::display::Display;
+use crate::instruction::Instruction;
+use std::fmt;
+use std::str::FromStr;
+
+/// Machine cycles the COSMAC VIP runs per 60 Hz frame: 1.76 MHz, 8 clocks a
+/// cycle.
+const VIP_CYCLES_PER_FRAME: i64 = 1_760_640 / 8 / FRAMES_PER_SECOND as i64;
+/// Cycles of each frame taken by the display DMA and the interrupt routine
+/// rather than the interpreter.
+const VIP_DISPLAY_CYCLES_PER_FRAME: i64 = 1024 + 46;
+
+/// Names accepted by [`Timing::from_str`].
+pub const TIMING_NAMES: [&str; 2] = ["ipf", "vip"];
+
+/// How much of the program runs in one frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Timing {
+    /// Every instruction takes the same time, a fixed number run per frame.
+    #[default]
+    InstructionsPerFrame,
+    /// Instructions take as long as on the COSMAC VIP, so a frame fits more
+    /// cheap instructions than slow ones like sprite drawing. Like on the VIP,
+    /// drawing a sprite also waits for the next frame, whatever the quirks.
+    VipCycles,
+}
+
+impl FromStr for Timing {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "ipf" => Ok(Self::InstructionsPerFrame),
+            "vip" => Ok(Self::VipCycles),
+            _ => Err(format!(
+                "Unknown timing {name}, expected one of: {}",
+                TIMING_NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InstructionsPerFrame => write!(f, "ipf"),
+            Self::VipCycles => write!(f, "vip"),
+        }
+    }
+}
+
+/// Decides how many instructions run in each frame.
+#[derive(Clone, Debug)]
+pub struct Scheduler {
+    timing: Timing,
+    instructions_per_frame: u32,
+    /// Cycles left in the current frame. An instruction that overruns the
+    /// frame is paid for by the next one.
+    cycle_budget: i64,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new(Timing::default(), DEFAULT_INSTRUCTIONS_PER_FRAME)
+    }
+}
+
+impl Scheduler {
+    pub fn new(timing: Timing, instructions_per_frame: u32) -> Self {
+        Self {
+            timing,
+            instructions_per_frame,
+            cycle_budget: 0,
+        }
+    }
+
+    pub fn get_timing(&self) -> Timing {
+        self.timing
+    }
+
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.timing = timing;
+        self.cycle_budget = 0;
+    }
+
+    pub fn get_instructions_per_frame(&self) -> u32 {
+        self.instructions_per_frame
+    }
+
+    /// Only used with [`Timing::InstructionsPerFrame`].
+    pub fn set_instructions_per_frame(&mut self, instructions_per_frame: u32) {
+        self.instructions_per_frame = instructions_per_frame;
+    }
+
+    /// Runs one frame of instructions, each through `step`, which returns
+    /// `false` when it didn't run it and the frame should stop, e.g. because
+    /// the debugger paused. Returns how many instructions ran.
+    pub fn run_frame<D: Display>(
+        &mut self,
+        cpu: &mut CPU<D>,
+        mut step: impl FnMut(&mut CPU<D>) -> bool,
+    ) -> u32 {
+        match self.timing {
+            Timing::InstructionsPerFrame => (0..self.instructions_per_frame)
+                .find(|_| !step(cpu))
+                .unwrap_or(self.instructions_per_frame),
+            Timing::VipCycles => {
+                self.cycle_budget += VIP_CYCLES_PER_FRAME - VIP_DISPLAY_CYCLES_PER_FRAME;
+                let mut instructions = 0;
+                while self.cycle_budget > 0 {
+                    // Waiting uses up the rest of the frame
+                    if cpu.is_waiting() {
+                        self.cycle_budget = 0;
+                        break;
+                    }
+                    let cycles = cpu.get_cycles();
+                    let program_counter = cpu.registers().get_program_counter();
+                    let is_draw = matches!(
+                        cpu.memory()
+                            .get_word(program_counter)
+                            .map(Instruction::decode),
+                        Ok(Instruction::Draw { .. })
+                    );
+                    if !step(cpu) {
+                        break;
+                    }
+                    instructions += 1;
+                    self.cycle_budget -= (cpu.get_cycles() - cycles) as i64;
+                    // The VIP interpreter syncs every sprite with the display
+                    // interrupt
+                    if is_draw {
+                        self.cycle_budget = 0;
+                        break;
+                    }
+                }
+                instructions
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cycles of 6XNN on the VIP, fetch included.
+    const LOAD_CYCLES: i64 = 68 + 6;
+
+    fn run_frames(rom: &[u8], timing: Timing, frames: usize) -> Vec<u32> {
+        let mut cpu = CPU::new();
+        cpu.load_rom(rom).unwrap();
+        let mut scheduler = Scheduler::new(timing, 10);
+        (0..frames)
+            .map(|_| scheduler.run_frame(&mut cpu, |cpu| cpu.step().is_ok()))
+            .collect()
+    }
+
+    #[test]
+    fn runs_vip_timing_until_the_frame_budget_is_spent() {
+        let rom = [0x60, 0x01].repeat(200);
+        let budget = VIP_CYCLES_PER_FRAME - VIP_DISPLAY_CYCLES_PER_FRAME;
+        // The instruction that overruns a frame is paid for by the next one
+        let first = (budget + LOAD_CYCLES - 1) / LOAD_CYCLES;
+        let overrun = first * LOAD_CYCLES - budget;
+        let second = (budget - overrun + LOAD_CYCLES - 1) / LOAD_CYCLES;
+        assert_eq!(
+            run_frames(&rom, Timing::VipCycles, 2),
+            [first as u32, second as u32]
+        );
+        assert_eq!(run_frames(&rom, Timing::InstructionsPerFrame, 2), [10, 10]);
+    }
+
+    #[test]
+    fn ends_the_vip_frame_after_drawing() {
+        let mut rom = vec![0x60, 0x01, 0xD0, 0x01];
+        rom.extend([0x60, 0x01].repeat(20));
+        assert_eq!(run_frames(&rom, Timing::VipCycles, 1), [2]);
+        assert_eq!(run_frames(&rom, Timing::InstructionsPerFrame, 1), [10]);
+    }
+}